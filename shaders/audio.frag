@@ -0,0 +1,15 @@
+#include <Nuance>
+
+// Bind a WAV or FLAC file to channel 0
+void main() {
+    vec2 uv = fragCoordNorm;
+    float spectrum = texture(uChannel0, vec2(uv.x, 0.25)).r;
+    float wave = texture(uChannel0, vec2(uv.x, 0.75)).r;
+
+    vec3 color = vec3(0.1, 0.1, 0.2) * (1.0 + 4.0 * fBass);
+    if (1.0 - uv.y < spectrum) {
+        color = mix(vec3(0.2, 0.4, 1.0), vec3(1.0, 0.3, 0.5), uv.x) * (0.5 + fMid);
+    }
+    color += vec3(1.0) * smoothstep(0.01, 0.0, abs(1.0 - uv.y - wave)) * (0.5 + fTreble);
+    fragColor = vec4(color, 1.0);
+}
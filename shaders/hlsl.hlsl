@@ -0,0 +1,17 @@
+#include <Nuance>
+
+[[vk::binding(0, 1)]]
+cbuffer Params {
+    // @color @init(0, 0, 1)
+    float3 background;
+    float radius; // @range(10, 200) @init(60)
+};
+
+float4 main(float4 fragCoord : SV_Position) : SV_Target {
+    float r = length(fragCoord.xy - float2(uMouse));
+    if (r <= radius * (fMouseWheel + 1.0)) {
+        return float4(noise(fragCoord.xy), 0.0, 0.0, 1.0);
+    } else {
+        return float4(background, 1.0);
+    }
+}
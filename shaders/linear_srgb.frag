@@ -19,7 +19,7 @@ void main() {
     vec3 a = vec3(0.0, 1.0, 0.0);
     vec3 b = vec3(1.0, 0.0, 0.0);
     if (fragCoord.y < uResolution.y / 3) {
-        // No correction (the correct one)
+        // No correction (the correct one with the sRGB encode output)
         fragColor = vec4(mix(a, b, fragCoord.x / uResolution.x), 1.0);
     } else if (fragCoord.y < uResolution.y * 2. / 3) {
         // To srgb
@@ -0,0 +1,15 @@
+#include <Nuance>
+
+// The output of paint.frag, which is rendered before this pass
+layout(pass = paint) uniform sampler2D canvas;
+
+void main() {
+    vec2 texel = 1.0 / vec2(uResolution);
+    vec4 color = vec4(0.0);
+    for (int x = -2; x <= 2; x++) {
+        for (int y = -2; y <= 2; y++) {
+            color += texture(canvas, fragCoordNorm + vec2(x, y) * texel);
+        }
+    }
+    fragColor = color / 25.0;
+}
@@ -0,0 +1,8 @@
+#include <Nuance>
+
+// Paint with the mouse, the paint slowly fades out
+void main() {
+    vec4 last = samplePrevious();
+    float brush = 1.0 - smoothstep(0.0, 20.0, distance(fragCoord.xy, vec2(uMouse)));
+    fragColor = vec4(max(last.rgb * 0.99, vec3(brush)), 1.0);
+}
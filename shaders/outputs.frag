@@ -0,0 +1,21 @@
+#include <Nuance>
+
+// Read back by Nuance and shown in the Outputs section of the side panel
+layout(output) buffer Results {
+    uint brightPixels;
+    float histogram[8];
+};
+
+void main() {
+    vec2 uv = fragCoordNorm;
+    float value = 0.5 + 0.5 * sin(10.0 * uv.x + fTime) * cos(10.0 * uv.y - fTime);
+    if (value > 0.9) {
+        atomicAdd(brightPixels, 1u);
+    }
+    // Floats can't be accumulated atomically, a single pixel writes each bin
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    if (pixel.y == 0 && pixel.x < 8) {
+        histogram[pixel.x] = 0.5 + 0.5 * sin(fTime + float(pixel.x));
+    }
+    fragColor = vec4(vec3(value), 1.0);
+}
@@ -0,0 +1,10 @@
+#include <Nuance>
+
+layout(params) uniform Params {
+    layout(color, init = vec3(1.0, 1.0, 1.0)) vec3 tint;
+};
+
+void main() {
+    float trail = texture(uChannel0, fragCoordNorm).r;
+    fragColor = vec4(tint * trail, 1.0);
+}
@@ -0,0 +1,8 @@
+#include <Nuance>
+
+// The mouse leaves a trail slowly fading out
+void main() {
+    float trail = samplePrevious().r * 0.98;
+    float brush = 1.0 - smoothstep(0.0, 30.0, distance(fragCoord.xy, vec2(uMouse)));
+    fragColor = vec4(max(trail, brush), 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,20 @@
+// A sound shader, render it to a WAV file from the Sound window
+
+#define PI 3.14159265
+
+// A decaying note at the given frequency
+float note(float frequency, float time) {
+    return sin(2.0 * PI * frequency * time) * exp(-3.0 * time);
+}
+
+vec2 mainSound(int samp, float time) {
+    float beat = mod(time, 0.5);
+    // A minor arpeggio
+    int arp = int(time / 0.5) % 4;
+    float semitones = arp == 0 ? 0.0 : arp == 1 ? 3.0 : arp == 2 ? 7.0 : 12.0;
+    float frequency = 220.0 * exp2(semitones / 12.0);
+    float tone = note(frequency, beat) + 0.5 * note(2.0 * frequency, beat);
+    // A kick on every beat
+    float kick = sin(2.0 * PI * 50.0 * beat * exp(-10.0 * beat)) * exp(-8.0 * beat);
+    return vec2(0.3 * tone + 0.5 * kick, 0.4 * tone + 0.5 * kick);
+}
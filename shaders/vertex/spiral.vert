@@ -0,0 +1,22 @@
+#include <NuanceVertex>
+
+#define NUANCE_VERTEX_COUNT 20000
+#define NUANCE_TOPOLOGY points
+#define NUANCE_BLEND additive
+
+layout(params) uniform Params {
+    layout(min = 1, max = 50, init = 12) float arms;
+    layout(min = 0, max = 2, init = 0.5) float speed;
+};
+
+void main() {
+    float t = vertexId / vertexCount;
+    float angle = t * arms * 6.2831853 + fTime * speed;
+    float radius = sqrt(t) * (0.8 + 0.1 * sin(t * 40.0 + fTime));
+    vec2 position = radius * vec2(cos(angle), sin(angle));
+    // Keep the spiral round whatever the canvas ratio
+    position.x /= fRatio;
+    gl_Position = vec4(position, 0.0, 1.0);
+    gl_PointSize = 1.0;
+    vColor = vec4(0.5 + 0.5 * cos(6.2831853 * t + vec3(0, 2, 4)), 1.0) * 0.5;
+}
@@ -1,6 +1,22 @@
 // No push constant support in wgsl
 
+// Params are declared with a struct bound at group 1 binding 0
+struct Params {
+    // @color @init(1, 0, 0)
+    color: vec3<f32>;
+    intensity: f32; // @range(0, 1) @init(1)
+    center: vec2<f32>; // @init(0.5, 0.5)
+    inverted: u32; // @bool
+};
+
+[[group(1), binding(0)]]
+var<uniform> params: Params;
+
 [[stage(fragment)]]
 fn main() -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    var color = params.color * params.intensity;
+    if (params.inverted != 0u) {
+        color = vec3<f32>(1.0) - color;
+    }
+    return vec4<f32>(color, 1.0);
 }
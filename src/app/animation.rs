@@ -0,0 +1,359 @@
+//! Encoding of exported animations
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use color_quant::NeuQuant;
+use image::{ImageBuffer, Rgba};
+use mint::Vector2;
+use nuance::offline::{FrameClock, Keyframes};
+use nuance::Slider;
+
+use crate::app::y4m::{self, Chroma, Matrix};
+
+/// File formats an animation can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    /// A PNG file per frame, numbered after the exported file name
+    PngSequence,
+    /// Uncompressed YUV4MPEG2 video
+    Y4m,
+    /// Raw RGBA frames written to the standard input of a command
+    Command,
+}
+
+impl AnimationFormat {
+    /// Extension of the exported file, commands don't write to a file
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            AnimationFormat::Gif => Some("gif"),
+            AnimationFormat::Apng | AnimationFormat::PngSequence => Some("png"),
+            AnimationFormat::Y4m => Some("y4m"),
+            AnimationFormat::Command => None,
+        }
+    }
+}
+
+/// How the colors of GIF frames are reduced to 256
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GifPalette {
+    /// Each frame has its own palette, colors may flicker between frames
+    PerFrame,
+    /// The same palette for all frames, computed from the first frame. Frames are opaque.
+    Global,
+}
+
+pub struct AnimationSettings {
+    pub format: AnimationFormat,
+    /// Time of the first frame in sec
+    pub start: f32,
+    /// Duration in sec
+    pub duration: f32,
+    pub fps: u32,
+    /// Play the animation in a loop, for GIF and APNG
+    pub repeat: bool,
+    pub gif_palette: GifPalette,
+    /// Speed of the palette quantization, from 1 (best colors) to 30 (fastest)
+    pub gif_speed: i32,
+    pub chroma: Chroma,
+    pub matrix: Matrix,
+    /// Command run by the shell, `{width}`, `{height}` and `{fps}` are replaced by their value
+    pub command: String,
+    /// Date at time 0 : year, month (1-12) and day
+    pub date: [i64; 3],
+    /// Values of the params over time
+    pub keyframes: Vec<Keyframes>,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            format: AnimationFormat::Gif,
+            start: 0.0,
+            duration: 5.0,
+            fps: 30,
+            repeat: true,
+            gif_palette: GifPalette::PerFrame,
+            gif_speed: 10,
+            chroma: Chroma::C420,
+            matrix: Matrix::Bt709,
+            command: "ffmpeg -y -f rawvideo -pix_fmt rgba -s {width}x{height} -r {fps} -i - -pix_fmt yuv420p render.mp4".to_string(),
+            date: {
+                let (year, month, day, _) = nuance::civil_date(SystemTime::now());
+                [year, month as i64, day as i64]
+            },
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl AnimationSettings {
+    pub fn frame_count(&self) -> u32 {
+        (self.duration.max(0.0) * self.fps as f32).round() as u32
+    }
+
+    /// Time and params of each frame
+    pub fn clock(&self) -> FrameClock {
+        let [year, month, day] = self.date;
+        let mut clock = FrameClock::new(
+            self.start as f64,
+            1.0 / self.fps as f64,
+            self.frame_count(),
+            nuance::date_from_civil(year, month as u32, day as u32),
+        );
+        clock.keyframes = self.keyframes.clone();
+        clock
+    }
+
+    /// Add a keyframe to each param with its current value
+    pub fn key_params(&mut self, time: f32, sliders: &[Slider]) {
+        for slider in sliders {
+            let index = match self
+                .keyframes
+                .iter()
+                .position(|it| it.param == slider.name())
+            {
+                Some(index) => index,
+                None => {
+                    self.keyframes.push(Keyframes::new(slider.name()));
+                    self.keyframes.len() - 1
+                }
+            };
+            self.keyframes[index].insert(time as f64, slider.value());
+        }
+    }
+
+    /// Times of all the keyframes, sorted
+    pub fn keyframe_times(&self) -> Vec<f64> {
+        let mut times: Vec<f64> = self
+            .keyframes
+            .iter()
+            .flat_map(|it| it.keys().iter().map(|(time, _)| *time))
+            .collect();
+        times.sort_by(f64::total_cmp);
+        times.dedup();
+        times
+    }
+
+    /// Remove the keyframes of all params at a time
+    pub fn remove_keyframes(&mut self, time: f64) {
+        for keyframes in self.keyframes.iter_mut() {
+            keyframes.remove(time);
+        }
+        self.keyframes.retain(|it| !it.keys().is_empty());
+    }
+}
+
+type Frame = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+/// Delay of a GIF frame in hundredths of a second, rounded from the time of the frames so the
+/// delays add up to the duration of the animation
+fn gif_delay(frame: u32, fps: u32) -> u16 {
+    let time = |frame: u32| (100.0 * frame as f64 / fps.max(1) as f64).round() as u64;
+    (time(frame + 1) - time(frame)).min(u16::MAX as u64) as u16
+}
+
+/// Writes the frames of an animation as they are rendered
+pub enum AnimationWriter {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        palette: GifPalette,
+        speed: i32,
+        /// The global palette and its colors, computed with the first frame
+        quantizer: Option<(NeuQuant, Vec<u8>)>,
+        fps: u32,
+        /// Index of the next frame, its delay depends on the time of the previous frames
+        frame: u32,
+    },
+    Apng(png::Writer<BufWriter<File>>),
+    PngSequence {
+        /// Path of the first frame without its number and extension
+        stem: PathBuf,
+        frame: u32,
+    },
+    Y4m {
+        writer: BufWriter<File>,
+        chroma: Chroma,
+        matrix: Matrix,
+    },
+    Command(Child),
+}
+
+impl AnimationWriter {
+    pub fn create(
+        path: &Path,
+        size: Vector2<u32>,
+        settings: &AnimationSettings,
+        frame_count: u32,
+    ) -> Result<Self> {
+        Ok(match settings.format {
+            AnimationFormat::Gif => {
+                let (width, height) = match (u16::try_from(size.x), u16::try_from(size.y)) {
+                    (Ok(width), Ok(height)) => (width, height),
+                    _ => return Err(anyhow!("GIF images can't be larger than 65535 px")),
+                };
+                let file = BufWriter::new(File::create(path)?);
+                // Each frame has its palette, even if it is the same for all frames
+                let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+                if settings.repeat {
+                    encoder.set_repeat(gif::Repeat::Infinite)?;
+                }
+                AnimationWriter::Gif {
+                    encoder,
+                    palette: settings.gif_palette,
+                    speed: settings.gif_speed.clamp(1, 30),
+                    quantizer: None,
+                    fps: settings.fps,
+                    frame: 0,
+                }
+            }
+            AnimationFormat::Apng => {
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder = png::Encoder::new(file, size.x, size.y);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frame_count, if settings.repeat { 0 } else { 1 })?;
+                encoder.set_frame_delay(1, settings.fps.min(u16::MAX as u32) as u16)?;
+                AnimationWriter::Apng(encoder.write_header()?)
+            }
+            AnimationFormat::PngSequence => AnimationWriter::PngSequence {
+                stem: path.with_extension(""),
+                frame: 0,
+            },
+            AnimationFormat::Y4m => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                y4m::write_header(&mut writer, (size.x, size.y), settings.fps, settings.chroma)?;
+                AnimationWriter::Y4m {
+                    writer,
+                    chroma: settings.chroma,
+                    matrix: settings.matrix,
+                }
+            }
+            AnimationFormat::Command => {
+                let command = settings
+                    .command
+                    .replace("{width}", &size.x.to_string())
+                    .replace("{height}", &size.y.to_string())
+                    .replace("{fps}", &settings.fps.to_string());
+                let (shell, flag) = if cfg!(windows) {
+                    ("cmd", "/C")
+                } else {
+                    ("sh", "-c")
+                };
+                let child = Command::new(shell)
+                    .args([flag, &command])
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .with_context(|| format!("Can't run {}", command))?;
+                AnimationWriter::Command(child)
+            }
+        })
+    }
+
+    pub fn write_frame(&mut self, image: Frame) -> Result<()> {
+        match self {
+            AnimationWriter::Gif {
+                encoder,
+                palette,
+                speed,
+                quantizer,
+                fps,
+                frame: index,
+            } => {
+                let (width, height) = (image.width() as u16, image.height() as u16);
+                let mut frame = match palette {
+                    GifPalette::PerFrame => {
+                        gif::Frame::from_rgba_speed(width, height, &mut image.into_raw(), *speed)
+                    }
+                    GifPalette::Global => {
+                        let (quantizer, colors) = quantizer.get_or_insert_with(|| {
+                            let quantizer = NeuQuant::new(*speed, 256, image.as_raw());
+                            let colors = quantizer.color_map_rgb();
+                            (quantizer, colors)
+                        });
+                        let indices = image
+                            .pixels()
+                            .map(|pixel| quantizer.index_of(&pixel.0) as u8)
+                            .collect();
+                        gif::Frame {
+                            width,
+                            height,
+                            buffer: Cow::Owned(indices),
+                            palette: Some(colors.clone()),
+                            ..Default::default()
+                        }
+                    }
+                };
+                frame.delay = gif_delay(*index, *fps);
+                encoder.write_frame(&frame)?;
+                *index += 1;
+            }
+            AnimationWriter::Apng(writer) => writer.write_image_data(image.as_raw())?,
+            AnimationWriter::PngSequence { stem, frame } => {
+                let path = PathBuf::from(format!("{}_{:04}.png", stem.display(), frame));
+                image
+                    .save_with_format(&path, image::ImageFormat::Png)
+                    .with_context(|| format!("Can't write {}", path.display()))?;
+                *frame += 1;
+            }
+            AnimationWriter::Y4m {
+                writer,
+                chroma,
+                matrix,
+            } => y4m::write_frame(writer, &image, *chroma, *matrix)?,
+            AnimationWriter::Command(child) => {
+                let written = match child.stdin.as_mut() {
+                    Some(stdin) => stdin.write_all(image.as_raw()),
+                    None => Err(io::ErrorKind::BrokenPipe.into()),
+                };
+                if let Err(e) = written {
+                    // The command exited or closed its input, it is reaped to report its status
+                    drop(child.stdin.take());
+                    let status = child.wait()?;
+                    return Err(anyhow!(e)
+                        .context(format!("The command stopped reading frames ({})", status)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self {
+            // The trailer is written when the encoder is dropped
+            AnimationWriter::Gif { .. } => {}
+            AnimationWriter::Apng(writer) => writer.finish()?,
+            AnimationWriter::PngSequence { .. } => {}
+            AnimationWriter::Y4m { mut writer, .. } => writer.flush()?,
+            AnimationWriter::Command(mut child) => {
+                // Closing its input ends the stream
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(anyhow!("The command failed with {}", status));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gif_delays_add_up_to_the_duration() {
+        let delays: Vec<_> = (0..30).map(|frame| gif_delay(frame, 30)).collect();
+        assert_eq!(delays.iter().map(|&it| it as u32).sum::<u32>(), 100);
+        assert!(delays.iter().all(|&it| it == 3 || it == 4));
+        assert!((0..24).all(|frame| gif_delay(frame, 25) == 4));
+    }
+}
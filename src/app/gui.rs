@@ -1,16 +1,28 @@
+use std::collections::HashMap;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use egui::plot::{Legend, Line, Plot, Value, Values};
 use egui::special_emojis::GITHUB;
-use egui::{ClippedMesh, Color32, DragValue, Frame, Id, TextureId, Ui};
-use egui::{Context, TexturesDelta};
+use egui::{pos2, vec2, ClippedMesh, Color32, DragValue, Frame, Id, Image, Rect, TextureId, Ui};
+use egui::{Align2, ColorImage, Context, Sense, TextStyle, TextureHandle, TexturesDelta};
 use egui_wgpu_backend::ScreenDescriptor;
 use image::ImageFormat;
+use shaderc::ShaderKind;
 use winit::event::WindowEvent;
 
-use nuance::Slider;
+use nuance::renderer::Tonemap;
+use nuance::{Channel, ColorSpace, Filter, OutputBlock, Slider, TargetFormat, Topology, Wrap};
 
-use crate::app::Nuance;
+use crate::app::animation::{AnimationFormat, GifPalette};
+use crate::app::hdr::BitDepth;
+use crate::app::outputs::{self, Outputs, OutputsFormat};
+use crate::app::y4m::{Chroma, Matrix};
+use crate::app::{channel_file, required_target_format, AlphaMode, Nuance};
+
+/// Size of the channel thumbnails
+const THUMBNAIL_SIZE: f32 = 64.0;
 
 pub struct Gui {
     /// Egui context
@@ -22,6 +34,11 @@ pub struct Gui {
     /// true if the profiling window should be open
     pub profiling_window: bool,
     export_window: bool,
+    sound_window: bool,
+    /// Show the outputs as plots instead of a table
+    outputs_plot: bool,
+    /// Thumbnails of the images bound to channels, None if the image can't be loaded
+    thumbnails: HashMap<PathBuf, Option<TextureHandle>>,
 }
 
 impl Gui {
@@ -32,9 +49,17 @@ impl Gui {
             ui_width,
             profiling_window: false,
             export_window: false,
+            sound_window: false,
+            outputs_plot: false,
+            thumbnails: HashMap::new(),
         }
     }
 
+    /// Forget the thumbnails, the images may have changed
+    pub fn clear_thumbnails(&mut self) {
+        self.thumbnails.clear();
+    }
+
     pub fn handle_event(&mut self, event: &WindowEvent) {
         self.egui_platform.on_event(&self.context, event);
     }
@@ -101,6 +126,56 @@ impl Gui {
             )
             .on_hover_text("The rate of change of the mouse wheel global");
 
+            let mut target_format = app.target_format();
+            // The output image format is compiled in compute shaders or set by the shader
+            let required = app.shader.as_ref().and_then(required_target_format).is_some();
+            ui.add_enabled_ui(!required, |ui| {
+                egui::ComboBox::from_label("target format")
+                    .selected_text(format!("{:?}", target_format))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut target_format, TargetFormat::Surface, "Surface");
+                        ui.selectable_value(&mut target_format, TargetFormat::Rgba16Float, "Rgba16Float");
+                        ui.selectable_value(&mut target_format, TargetFormat::Rgba32Float, "Rgba32Float");
+                    })
+                    .response
+                    .on_hover_text("The format of the texture the shader renders to, float targets keep values outside of 0-1")
+                    .on_disabled_hover_text("The shader sets its target format with NUANCE_TARGET_FORMAT, compute shaders default to Surface");
+            });
+            if target_format != app.target_format() {
+                app.set_target_format(target_format);
+            }
+            let mut color_space = app.settings.color_space;
+            egui::ComboBox::from_label("output")
+                .selected_text(match color_space {
+                    ColorSpace::Srgb => "sRGB encode",
+                    ColorSpace::Passthrough => "passthrough",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut color_space, ColorSpace::Srgb, "sRGB encode");
+                    ui.selectable_value(&mut color_space, ColorSpace::Passthrough, "passthrough");
+                })
+                .response
+                .on_hover_text("sRGB encode : fragColor is linear and encoded to sRGB. Passthrough : fragColor is shown and exported as is. Applies to the canvas and exports.");
+            if color_space != app.settings.color_space {
+                app.set_color_space(color_space);
+            }
+            if target_format != TargetFormat::Surface {
+                let mut display = app.settings.display;
+                egui::ComboBox::from_label("tonemap")
+                    .selected_text(format!("{:?}", display.tonemap))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut display.tonemap, Tonemap::None, "None");
+                        ui.selectable_value(&mut display.tonemap, Tonemap::Reinhard, "Reinhard");
+                        ui.selectable_value(&mut display.tonemap, Tonemap::Aces, "Aces");
+                    })
+                    .response
+                    .on_hover_text("How the float output is mapped to displayable colors");
+                ui.checkbox(&mut display.clamp, "clamp").on_hover_text("Clamp the displayed colors to 0-1");
+                if display != app.settings.display {
+                    app.set_display_settings(display);
+                }
+            }
+
             ui.separator();
 
             ui.horizontal(|ui| {
@@ -122,14 +197,214 @@ impl Gui {
                         app.gui.export_window = true;
                     }
                 }
+                if ui.button("Sound").on_hover_text("Opens a window to render a sound shader to a WAV file").clicked() {
+                    app.gui.sound_window = true;
+                }
             });
 
             // Shader name
+            let mut new_entry_point = None;
+            let mut new_vertex = None;
             if let Some(shader) = app.shader.as_ref() {
                 ui.colored_label(Color32::GREEN, shader.main.to_str().unwrap());
+                if shader.entry_points.len() > 1 {
+                    egui::ComboBox::from_label("entry point")
+                        .selected_text(shader.entry_point())
+                        .show_ui(ui, |ui| {
+                            for entry_point in shader.entry_points.iter() {
+                                if ui
+                                    .selectable_label(entry_point == shader.entry_point(), entry_point)
+                                    .clicked()
+                                {
+                                    new_entry_point = Some(entry_point.clone());
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("The fragment entry point to run, the module contains several");
+                }
+                if shader.passes.len() > 1 || shader.passes.iter().any(|it| it.channels.iter().any(|it| *it != Channel::Empty)) {
+                    ui.collapsing("Passes", |ui| {
+                        for (i, pass) in shader.passes.iter().enumerate() {
+                            let inputs: Vec<String> = pass.channels.iter().enumerate().filter_map(|(channel, input)| match input {
+                                Channel::Pass { index, previous } => Some(format!(
+                                    "channel {} : {}{}",
+                                    channel,
+                                    shader.passes[*index].name,
+                                    if *previous { " (previous frame)" } else { "" }
+                                )),
+                                Channel::Texture(path) | Channel::Audio(path) => Some(format!("channel {} : {}", channel, path.display())),
+                                Channel::Empty => None,
+                            }).collect();
+                            ui.label(format!("{}. {}", i + 1, pass.name)).on_hover_text(if inputs.is_empty() {
+                                "No inputs".to_string()
+                            } else {
+                                inputs.join("\n")
+                            });
+                        }
+                    }).header_response.on_hover_text("Passes are rendered in this order, the last one is displayed");
+                }
+                for (pass, channel) in app.renderer.unfilterable_channels() {
+                    ui.colored_label(Color32::RED, format!("× Channel {} of {} can't sample the Rgba32Float displayed pass", channel, shader.passes[pass].name))
+                        .on_hover_text("Rgba32Float textures can't be filtered, the channel is bound to an empty texture. Use Rgba16Float to sample the displayed pass.");
+                }
+                if let (ShaderKind::Vertex, Some(metadata)) = (shader.kind, shader.metadata.as_ref()) {
+                    let mut edited = metadata.vertex;
+                    ui.collapsing("Vertices", |ui| {
+                        ui.add(DragValue::new(&mut edited.count).prefix("count : ").clamp_range(1..=10_000_000).speed(10.0))
+                            .on_hover_text("Number of vertices drawn each frame, the shader gets it as vertexCount");
+                        egui::ComboBox::from_label("topology")
+                            .selected_text(format!("{:?}", edited.topology))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut edited.topology, Topology::Points, "Points");
+                                ui.selectable_value(&mut edited.topology, Topology::Lines, "Lines");
+                                ui.selectable_value(&mut edited.topology, Topology::LineStrip, "LineStrip");
+                                ui.selectable_value(&mut edited.topology, Topology::Triangles, "Triangles");
+                                ui.selectable_value(&mut edited.topology, Topology::TriangleStrip, "TriangleStrip");
+                            })
+                            .response
+                            .on_hover_text("How the vertices are assembled into primitives");
+                        ui.checkbox(&mut edited.msaa, "MSAA").on_hover_text("Smooth the edges of the primitives with 4x multisampling");
+                        ui.checkbox(&mut edited.additive, "additive").on_hover_text("Add the colors of overlapping primitives");
+                    }).header_response.on_hover_text("How the primitives of the vertex shader are drawn");
+                    if edited != metadata.vertex {
+                        new_vertex = Some(edited);
+                    }
+                }
             } else {
                 ui.colored_label(Color32::RED, "No shader");
             }
+            if let Some(entry_point) = new_entry_point {
+                app.set_entry_point(entry_point);
+            }
+            if let Some(vertex) = new_vertex {
+                app.set_vertex_settings(vertex);
+            }
+
+            // Channels of the displayed pass
+            let mut new_channel = None;
+            let mut new_sampler = None;
+            let mut new_previous = None;
+            let mut pick_channel = None;
+            if let Some(shader) = app.shader.as_ref() {
+                let pass = shader.passes.last().unwrap();
+                let thumbnails = &mut app.gui.thumbnails;
+                ui.collapsing("Channels", |ui| {
+                    // Images can be dropped on a slot
+                    let (hovering_files, dropped, pointer) = {
+                        let input = ui.input();
+                        (
+                            !input.raw.hovered_files.is_empty(),
+                            input.raw.dropped_files.iter().find_map(|it| it.path.clone()),
+                            input.pointer.hover_pos(),
+                        )
+                    };
+                    for (i, (channel, sampler)) in pass.channels.iter().zip(pass.samplers.iter()).enumerate() {
+                        ui.horizontal(|ui| {
+                            let (rect, response) = ui.allocate_exact_size(vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE), Sense::click());
+                            ui.painter().rect_filled(rect, 0.0, Color32::BLACK);
+                            let description = match channel {
+                                Channel::Empty => "empty".to_string(),
+                                Channel::Pass { index, previous } => format!(
+                                    "{}{}",
+                                    shader.passes[*index].name,
+                                    if *previous { " (previous frame)" } else { "" }
+                                ),
+                                Channel::Texture(path) => {
+                                    if let Some(thumbnail) = thumbnails.entry(path.clone()).or_insert_with(|| load_thumbnail(ui.ctx(), path)) {
+                                        let size = thumbnail.size_vec2() * THUMBNAIL_SIZE / thumbnail.size_vec2().max_elem();
+                                        Image::new(thumbnail.id(), size).paint_at(ui, Rect::from_center_size(rect.center(), size));
+                                    }
+                                    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+                                }
+                                Channel::Audio(path) => {
+                                    ui.painter().text(rect.center(), Align2::CENTER_CENTER, "🎵", TextStyle::Heading.resolve(ui.style()), Color32::WHITE);
+                                    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+                                }
+                            };
+
+                            // Channels bound to passes are fixed by the shader
+                            if !matches!(channel, Channel::Pass { .. }) {
+                                if pointer.is_some_and(|it| rect.contains(it)) {
+                                    if hovering_files {
+                                        ui.painter().rect_stroke(rect, 0.0, (2.0, Color32::GREEN));
+                                    }
+                                    if let Some(path) = &dropped {
+                                        new_channel = Some((i, channel_file(path.clone())));
+                                    }
+                                }
+                                if response.on_hover_text("Click or drop an image or an audio file to bind it to this channel").clicked() {
+                                    pick_channel = Some(i);
+                                }
+                            }
+
+                            ui.vertical(|ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("uChannel{} : {}", i, description));
+                                    if matches!(channel, Channel::Texture(_) | Channel::Audio(_)) && ui.small_button("x").on_hover_text("Unbind the file").clicked() {
+                                        new_channel = Some((i, Channel::Empty));
+                                    }
+                                });
+                                let mut edited = *sampler;
+                                egui::ComboBox::from_id_source(("channel filter", i))
+                                    .selected_text(format!("{:?}", edited.filter))
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut edited.filter, Filter::Nearest, "Nearest");
+                                        ui.selectable_value(&mut edited.filter, Filter::Linear, "Linear");
+                                    })
+                                    .response
+                                    .on_hover_text("Texture filtering");
+                                egui::ComboBox::from_id_source(("channel wrap", i))
+                                    .selected_text(format!("{:?}", edited.wrap))
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut edited.wrap, Wrap::Clamp, "Clamp");
+                                        ui.selectable_value(&mut edited.wrap, Wrap::Repeat, "Repeat");
+                                        ui.selectable_value(&mut edited.wrap, Wrap::Mirror, "Mirror");
+                                    })
+                                    .response
+                                    .on_hover_text("Sampling outside of the texture");
+                                ui.checkbox(&mut edited.mipmaps, "mipmaps").on_hover_text("Sample the mipmaps of images");
+                                if edited != *sampler {
+                                    new_sampler = Some((i, edited));
+                                }
+                            });
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("previous frame").on_hover_text("How samplePrevious samples the previous frame");
+                        let mut edited = pass.previous;
+                        egui::ComboBox::from_id_source("previous filter")
+                            .selected_text(format!("{:?}", edited.filter))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut edited.filter, Filter::Nearest, "Nearest");
+                                ui.selectable_value(&mut edited.filter, Filter::Linear, "Linear");
+                            });
+                        egui::ComboBox::from_id_source("previous wrap")
+                            .selected_text(format!("{:?}", edited.wrap))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut edited.wrap, Wrap::Clamp, "Clamp");
+                                ui.selectable_value(&mut edited.wrap, Wrap::Repeat, "Repeat");
+                                ui.selectable_value(&mut edited.wrap, Wrap::Mirror, "Mirror");
+                            });
+                        if edited != pass.previous {
+                            new_previous = Some(edited);
+                        }
+                    });
+                }).header_response.on_hover_text("Inputs of the displayed pass, sampled with uChannel0 to uChannel3");
+            }
+            if let Some((channel, input)) = new_channel {
+                app.set_channel(channel, input);
+            }
+            if let Some((channel, sampler)) = new_sampler {
+                app.set_channel_sampler(channel, sampler);
+            }
+            if let Some(previous) = new_previous {
+                app.set_previous_sampler(previous);
+            }
+            if let Some(channel) = pick_channel {
+                app.ask_channel_image(channel);
+            }
 
             if app.shader_loaded() && ui.selectable_label(app.is_paused(), "Pause").on_hover_text("Pause the current shader execution").clicked() {
                 if app.is_paused() {
@@ -164,6 +439,41 @@ impl Gui {
                 app.reset_params();
             }
 
+            let mut export_outputs = None;
+            if let Some(block) = app.shader.as_ref().and_then(|it| it.metadata.as_ref()).and_then(|it| it.output.as_ref()) {
+                ui.separator();
+                let outputs_plot = &mut app.gui.outputs_plot;
+                let readback_interval = &mut app.settings.readback_interval;
+                let outputs = &app.outputs;
+                ui.collapsing("Outputs", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("every");
+                        ui.add(DragValue::new(readback_interval).clamp_range(1..=600).suffix(" frames"))
+                            .on_hover_text("Number of frames between two read backs of the output buffer");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(outputs_plot, false, "Table");
+                        ui.selectable_value(outputs_plot, true, "Plot");
+                        ui.add_enabled_ui(!outputs.is_empty(), |ui| {
+                            if ui.button("CSV").on_hover_text("Export the read back values to a CSV file").clicked() {
+                                export_outputs = Some(OutputsFormat::Csv);
+                            }
+                            if ui.button("JSON").on_hover_text("Export the read back values to a JSON file").clicked() {
+                                export_outputs = Some(OutputsFormat::Json);
+                            }
+                        });
+                    });
+                    if *outputs_plot {
+                        draw_outputs_plots(ui, block, outputs);
+                    } else {
+                        draw_outputs_table(ui, block, outputs);
+                    }
+                }).header_response.on_hover_text(format!("Values written by the shader to {}", block.name));
+            }
+            if let Some(format) = export_outputs {
+                app.ask_to_export_outputs(format);
+            }
+
             ui.add_space(ui.available_size().y - 2.0 * ui.spacing().item_spacing.y - 30.0);
             ui.vertical_centered(|ui| {
                 ui.hyperlink_to(
@@ -184,37 +494,104 @@ impl Gui {
         //log::info!("{:?}", app.gui.ui_width);
         //log::info!("{:?}", app.gui.context().used_size());
 
+        // Shadertoy shaders are rendered upside down
+        let canvas_uv = if app.shader_metadata().is_some_and(|it| it.shadertoy) {
+            Rect::from_min_max(pos2(0.0, 1.0), pos2(1.0, 0.0))
+        } else {
+            Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0))
+        };
         egui::CentralPanel::default()
             .frame(Frame::none())
             .show(&app.gui.context, |ui| {
-                ui.image(
-                    TextureId::User(0),
-                    egui::Vec2::new(
-                        window.physical_width as f32 / window.scale_factor - side_panel.rect.max.x,
-                        window.physical_height as f32 / window.scale_factor,
-                    ),
+                ui.add(
+                    Image::new(
+                        TextureId::User(0),
+                        egui::Vec2::new(
+                            window.physical_width as f32 / window.scale_factor
+                                - side_panel.rect.max.x,
+                            window.physical_height as f32 / window.scale_factor,
+                        ),
+                    )
+                    .uv(canvas_uv),
                 );
             });
 
         let mut should_ask_export = false;
+        let mut should_key_params = false;
 
+        // Compute shaders can't be rendered again to a float texture
+        let float_export = app.target_format() != TargetFormat::Surface
+            || app.shader.as_ref().is_some_and(|it| it.kind != ShaderKind::Compute);
         let format_ref = &mut app.export_data.format;
+        let bit_depth_ref = &mut app.export_data.bit_depth;
         let size_x_ref = &mut app.export_data.size.x;
         let size_y_ref = &mut app.export_data.size.y;
+        let animated_ref = &mut app.export_data.animated;
+        let animation = &mut app.export_data.animation;
+        let alpha_ref = &mut app.export_data.alpha;
         egui::Window::new("Export image")
             .id(Id::new("export image window"))
             .open(&mut app.gui.export_window)
             .collapsible(false)
             .resizable(false)
             .show(&app.gui.context, |ui| {
-                egui::ComboBox::from_label("format")
-                    .selected_text(format_ref.extensions_str()[0])
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(format_ref, ImageFormat::Png, "PNG");
-                        ui.selectable_value(format_ref, ImageFormat::Bmp, "BMP");
-                        ui.selectable_value(format_ref, ImageFormat::Gif, "GIF");
-                        ui.selectable_value(format_ref, ImageFormat::Jpeg, "JPEG");
-                    });
+                ui.horizontal(|ui| {
+                    ui.selectable_value(animated_ref, false, "Image");
+                    ui.selectable_value(animated_ref, true, "Animation");
+                });
+
+                if *animated_ref {
+                    egui::ComboBox::from_label("format")
+                        .selected_text(format!("{:?}", animation.format))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut animation.format, AnimationFormat::Gif, "GIF");
+                            ui.selectable_value(
+                                &mut animation.format,
+                                AnimationFormat::Apng,
+                                "APNG",
+                            );
+                            ui.selectable_value(
+                                &mut animation.format,
+                                AnimationFormat::PngSequence,
+                                "PNG sequence",
+                            );
+                            ui.selectable_value(&mut animation.format, AnimationFormat::Y4m, "Y4M");
+                            ui.selectable_value(
+                                &mut animation.format,
+                                AnimationFormat::Command,
+                                "Command",
+                            );
+                        });
+                } else {
+                    egui::ComboBox::from_label("format")
+                        .selected_text(format_ref.extensions_str()[0])
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(format_ref, ImageFormat::Png, "PNG");
+                            ui.selectable_value(format_ref, ImageFormat::Bmp, "BMP");
+                            ui.selectable_value(format_ref, ImageFormat::Gif, "GIF");
+                            ui.selectable_value(format_ref, ImageFormat::Jpeg, "JPEG");
+                            ui.selectable_value(format_ref, ImageFormat::Tiff, "TIFF");
+                            ui.selectable_value(format_ref, ImageFormat::OpenExr, "OpenEXR");
+                        });
+                    let supported = BitDepth::supported(*format_ref);
+                    if !supported.contains(bit_depth_ref) {
+                        *bit_depth_ref = supported[0];
+                    }
+                    if supported.len() > 1 {
+                        egui::ComboBox::from_label("depth")
+                            .selected_text(bit_depth_ref.label(*format_ref))
+                            .show_ui(ui, |ui| {
+                                for depth in supported {
+                                    ui.selectable_value(bit_depth_ref, *depth, depth.label(*format_ref));
+                                }
+                            })
+                            .response
+                            .on_hover_text("High bit depths are read from the float target without the tonemap, or rendered to a half float texture without a float target. 16 bits images are clamped to 0-1");
+                        if *bit_depth_ref != BitDepth::Eight && !float_export {
+                            ui.colored_label(Color32::RED, "× Compute shaders export high bit depths with a float NUANCE_TARGET_FORMAT");
+                        }
+                    }
+                }
 
                 ui.horizontal(|ui| {
                     ui.label("Size :");
@@ -222,8 +599,128 @@ impl Gui {
                     ui.label("x");
                     ui.add(DragValue::new(size_y_ref).suffix("px"));
                 });
-                if *size_x_ref % 64 != 0 {
-                    ui.colored_label(Color32::RED, "× Image width must be a multiple of 64");
+
+                egui::ComboBox::from_label("alpha")
+                    .selected_text(format!("{:?}", alpha_ref))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(alpha_ref, AlphaMode::Straight, "Straight");
+                        ui.selectable_value(alpha_ref, AlphaMode::Premultiplied, "Premultiplied");
+                        ui.selectable_value(alpha_ref, AlphaMode::Opaque, "Opaque");
+                    })
+                    .response
+                    .on_hover_text("Straight keeps the alpha written by the shader, premultiplied multiplies the colors by alpha and opaque ignores alpha like the canvas. JPEG, BMP and Y4M have no alpha channel.");
+
+                if *animated_ref {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut animation.start)
+                                .prefix("start : ")
+                                .suffix(" s")
+                                .clamp_range(0.0..=3600.0)
+                                .speed(0.1),
+                        );
+                        ui.add(
+                            DragValue::new(&mut animation.duration)
+                                .prefix("duration : ")
+                                .suffix(" s")
+                                .clamp_range(0.1..=600.0)
+                                .speed(0.1),
+                        );
+                        ui.add(
+                            DragValue::new(&mut animation.fps)
+                                .suffix(" fps")
+                                .clamp_range(1..=120),
+                        );
+                    })
+                    .response
+                    .on_hover_text("uFrame counts from 0 and fTime advances by exactly one frame from the start time");
+                    if matches!(animation.format, AnimationFormat::Gif | AnimationFormat::Apng) {
+                        ui.checkbox(&mut animation.repeat, "loop");
+                    }
+                    if animation.format == AnimationFormat::Gif {
+                        egui::ComboBox::from_label("palette")
+                            .selected_text(match animation.gif_palette {
+                                GifPalette::PerFrame => "per frame",
+                                GifPalette::Global => "global",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut animation.gif_palette,
+                                    GifPalette::PerFrame,
+                                    "per frame",
+                                );
+                                ui.selectable_value(
+                                    &mut animation.gif_palette,
+                                    GifPalette::Global,
+                                    "global",
+                                );
+                            })
+                            .response
+                            .on_hover_text("Per frame palettes have the best colors but may flicker, the global palette is computed from the first frame");
+                        ui.add(
+                            DragValue::new(&mut animation.gif_speed)
+                                .prefix("quantization speed : ")
+                                .clamp_range(1..=30),
+                        )
+                        .on_hover_text("1 gives the best colors, 30 is the fastest");
+                    }
+                    if animation.format == AnimationFormat::Y4m {
+                        egui::ComboBox::from_label("chroma")
+                            .selected_text(match animation.chroma {
+                                Chroma::C444 => "4:4:4",
+                                Chroma::C422 => "4:2:2",
+                                Chroma::C420 => "4:2:0",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut animation.chroma, Chroma::C444, "4:4:4");
+                                ui.selectable_value(&mut animation.chroma, Chroma::C422, "4:2:2");
+                                ui.selectable_value(&mut animation.chroma, Chroma::C420, "4:2:0");
+                            })
+                            .response
+                            .on_hover_text("Resolution of the color planes, 4:2:0 is the most compatible");
+                        egui::ComboBox::from_label("matrix")
+                            .selected_text(match animation.matrix {
+                                Matrix::Bt601 => "BT.601",
+                                Matrix::Bt709 => "BT.709",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut animation.matrix, Matrix::Bt601, "BT.601");
+                                ui.selectable_value(&mut animation.matrix, Matrix::Bt709, "BT.709");
+                            });
+                    }
+                    if animation.format == AnimationFormat::Command {
+                        ui.text_edit_singleline(&mut animation.command)
+                            .on_hover_text("Raw RGBA frames are written to the standard input of this command. {width}, {height} and {fps} are replaced by their value.");
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("date :");
+                        ui.add(DragValue::new(&mut animation.date[0]).clamp_range(1970..=9999));
+                        ui.add(DragValue::new(&mut animation.date[1]).clamp_range(1..=12));
+                        ui.add(DragValue::new(&mut animation.date[2]).clamp_range(1..=31));
+                    })
+                    .response
+                    .on_hover_text("Year, month and day at fTime = 0, iDate advances with fTime");
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("key params")
+                            .on_hover_text("Add a keyframe with the current params at the current fTime, params are interpolated between keyframes")
+                            .clicked()
+                        {
+                            should_key_params = true;
+                        }
+                        if ui.button("clear").clicked() {
+                            animation.keyframes.clear();
+                        }
+                    });
+                    for time in animation.keyframe_times() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("keyframe at {:.2} s", time));
+                            if ui.small_button("×").clicked() {
+                                animation.remove_keyframes(time);
+                            }
+                        });
+                    }
+                    ui.label(format!("{} frames", animation.frame_count()));
                 }
 
                 if ui.button("export").clicked() {
@@ -234,6 +731,73 @@ impl Gui {
         if should_ask_export {
             app.ask_to_export();
         }
+        if should_key_params {
+            if let Some(metadata) = app.shader.as_ref().and_then(|it| it.metadata.as_ref()) {
+                app.export_data
+                    .animation
+                    .key_params(app.globals.time, &metadata.sliders);
+            }
+        }
+
+        let mut should_ask_sound_shader = false;
+        let mut should_ask_sound_export = false;
+
+        let sound_shader = app.sound_data.shader.as_ref();
+        let sound_settings = &mut app.sound_data.settings;
+        egui::Window::new("Export sound")
+            .id(Id::new("export sound window"))
+            .open(&mut app.gui.sound_window)
+            .collapsible(false)
+            .resizable(false)
+            .show(&app.gui.context, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Shader :");
+                    let name = sound_shader
+                        .and_then(|it| it.file_name())
+                        .map_or("none".to_string(), |it| it.to_string_lossy().into_owned());
+                    if ui
+                        .button(name)
+                        .on_hover_text(
+                            "Pick a glsl file declaring vec2 mainSound(int samp, float time)",
+                        )
+                        .clicked()
+                    {
+                        should_ask_sound_shader = true;
+                    }
+                });
+                ui.add(
+                    DragValue::new(&mut sound_settings.duration)
+                        .prefix("duration : ")
+                        .suffix(" s")
+                        .clamp_range(0.1..=600.0)
+                        .speed(0.1),
+                );
+                egui::ComboBox::from_label("sample rate")
+                    .selected_text(format!("{} Hz", sound_settings.sample_rate))
+                    .show_ui(ui, |ui| {
+                        for rate in [22050, 44100, 48000, 96000] {
+                            ui.selectable_value(
+                                &mut sound_settings.sample_rate,
+                                rate,
+                                format!("{} Hz", rate),
+                            );
+                        }
+                    });
+
+                if ui
+                    .add_enabled(sound_shader.is_some(), egui::Button::new("export"))
+                    .clicked()
+                {
+                    should_ask_sound_export = true;
+                }
+            });
+
+        if should_ask_sound_shader {
+            app.ask_sound_shader();
+        }
+        if should_ask_sound_export {
+            app.ask_to_export_sound();
+        }
 
         #[cfg(feature = "puffin_egui")]
         if app.gui.profiling_window {
@@ -258,6 +822,19 @@ impl Gui {
     }
 }
 
+/// Load a small version of an image to display it
+fn load_thumbnail(context: &Context, path: &Path) -> Option<TextureHandle> {
+    let image = image::open(path)
+        .ok()?
+        .thumbnail(THUMBNAIL_SIZE as u32, THUMBNAIL_SIZE as u32)
+        .to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    Some(context.load_texture(
+        path.to_string_lossy(),
+        ColorImage::from_rgba_unmultiplied(size, &image),
+    ))
+}
+
 fn draw_slider(slider: &mut Slider, ui: &mut Ui) {
     match slider {
         Slider::Float {
@@ -323,3 +900,68 @@ fn draw_slider(slider: &mut Slider, ui: &mut Ui) {
         }
     }
 }
+
+/// The latest values of each field
+fn draw_outputs_table(ui: &mut Ui, block: &OutputBlock, outputs: &Outputs) {
+    let latest = match outputs.latest() {
+        Some(latest) => latest,
+        None => {
+            ui.label("Waiting for the first read back");
+            return;
+        }
+    };
+    ui.label(format!("frame {}", latest.frame));
+    egui::Grid::new("outputs grid")
+        .striped(true)
+        .show(ui, |ui| {
+            for (field, values) in block.fields.iter().zip(&latest.values) {
+                for (name, value) in outputs::value_names(field).iter().zip(values) {
+                    ui.label(name);
+                    ui.label(format!("{}", value));
+                    ui.end_row();
+                }
+            }
+        });
+}
+
+/// Arrays are plotted over their index, other fields over the frames of the read backs
+fn draw_outputs_plots(ui: &mut Ui, block: &OutputBlock, outputs: &Outputs) {
+    for (i, field) in block.fields.iter().enumerate() {
+        let components = field.components as usize;
+        let lines: Vec<Line> = (0..components)
+            .map(|c| {
+                let values = match (field.length, outputs.latest()) {
+                    (Some(_), Some(latest)) => Values::from_values_iter(
+                        latest.values[i]
+                            .iter()
+                            .skip(c)
+                            .step_by(components)
+                            .enumerate()
+                            .map(|(x, y)| Value::new(x as f64, *y)),
+                    ),
+                    (Some(_), None) => Values::from_values(Vec::new()),
+                    (None, _) => Values::from_values_iter(
+                        outputs
+                            .history()
+                            .map(|it| Value::new(it.frame, it.values[i][c])),
+                    ),
+                };
+                let name = if components == 1 {
+                    field.name.clone()
+                } else {
+                    format!("{}.{}", field.name, outputs::COMPONENTS[c])
+                };
+                Line::new(values).name(name)
+            })
+            .collect();
+        ui.label(&field.name);
+        Plot::new(("outputs plot", i))
+            .height(100.0)
+            .legend(Legend::default())
+            .show(ui, |plot| {
+                for line in lines {
+                    plot.line(line);
+                }
+            });
+    }
+}
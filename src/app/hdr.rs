@@ -0,0 +1,93 @@
+//! High bit depth image exports, from the float output of the displayed pass
+
+use std::path::Path;
+
+use anyhow::Result;
+use half::f16;
+use image::{ImageBuffer, ImageFormat, Rgba};
+
+use nuance::ColorSpace;
+
+/// Pixels read back from a float target
+pub type FloatImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// Bits per channel of exported images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    /// The displayed colors
+    Eight,
+    /// Integers, or half floats for OpenEXR
+    Sixteen,
+    /// Floats, for OpenEXR
+    ThirtyTwo,
+}
+
+impl BitDepth {
+    /// Bit depths an image format can be exported with, the first is the default
+    pub fn supported(format: ImageFormat) -> &'static [BitDepth] {
+        match format {
+            ImageFormat::Png | ImageFormat::Tiff => &[BitDepth::Eight, BitDepth::Sixteen],
+            ImageFormat::OpenExr => &[BitDepth::Sixteen, BitDepth::ThirtyTwo],
+            _ => &[BitDepth::Eight],
+        }
+    }
+
+    pub fn label(&self, format: ImageFormat) -> &'static str {
+        match (self, format) {
+            (BitDepth::Eight, _) => "8 bits",
+            (BitDepth::Sixteen, ImageFormat::OpenExr) => "half float",
+            (BitDepth::Sixteen, _) => "16 bits",
+            (BitDepth::ThirtyTwo, _) => "float",
+        }
+    }
+}
+
+/// Encode a linear value to sRGB
+fn srgb_encode(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Write a 16 bits PNG or TIFF. Colors are clamped to 0-1 and encoded to sRGB unless they are
+/// passthrough, alpha is always linear.
+pub fn save_16(
+    image: &FloatImage,
+    path: &Path,
+    format: ImageFormat,
+    color_space: ColorSpace,
+) -> Result<()> {
+    let mut encoded = ImageBuffer::<Rgba<u16>, _>::new(image.width(), image.height());
+    for (out, pixel) in encoded.pixels_mut().zip(image.pixels()) {
+        for (i, (out, value)) in out.0.iter_mut().zip(pixel.0).enumerate() {
+            let value = if i < 3 && color_space == ColorSpace::Srgb {
+                srgb_encode(value.max(0.0))
+            } else {
+                value
+            };
+            *out = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        }
+    }
+    encoded.save_with_format(path, format)?;
+    Ok(())
+}
+
+/// Write an OpenEXR image with half or full floats, colors are written as is
+pub fn save_exr(image: &FloatImage, path: &Path, depth: BitDepth) -> Result<()> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let pixel = |x: usize, y: usize| image.get_pixel(x as u32, y as u32).0;
+    if depth == BitDepth::Sixteen {
+        exr::prelude::write_rgba_file(path, width, height, |x, y| {
+            let [r, g, b, a] = pixel(x, y).map(f16::from_f32);
+            (r, g, b, a)
+        })?;
+    } else {
+        exr::prelude::write_rgba_file(path, width, height, |x, y| {
+            let [r, g, b, a] = pixel(x, y);
+            (r, g, b, a)
+        })?;
+    }
+    Ok(())
+}
@@ -1,40 +1,68 @@
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::mpsc::Receiver;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{Context, Result};
 use crevice::std430::AsStd430;
 use crevice::std430::Std430;
 use egui_wgpu_backend::ScreenDescriptor;
-use image::{ImageBuffer, ImageFormat, Rgba};
+use image::{imageops, DynamicImage, ImageFormat, RgbaImage};
 use log::{debug, error, info};
-use mint::Vector2;
+use mint::{Vector2, Vector4};
 use notify::{watcher, DebouncedEvent, Error, RecommendedWatcher, RecursiveMode, Watcher};
 use rfd::FileDialog;
+use shaderc::ShaderKind;
 use winit::event::{MouseScrollDelta, VirtualKeyCode, WindowEvent};
 use winit::event_loop::ControlFlow;
 use winit::window::Window;
 
 use nuance::loader::ShaderLoader;
-use nuance::Globals;
+use nuance::renderer::{DisplaySettings, CHANNEL_COUNT};
+use nuance::sound::{self, SoundSettings};
+use nuance::{
+    Channel, ChannelSampler, ColorSpace, Globals, Pass, PreviousSampler, TargetFormat,
+    VertexSettings,
+};
 use nuance::{Shader, ShaderMetadata};
 
+use crate::app::animation::{AnimationSettings, AnimationWriter};
 use crate::app::gui::Gui;
+use crate::app::hdr::{BitDepth, FloatImage};
+use crate::app::outputs::{Outputs, OutputsFormat};
 use crate::app::renderer::Renderer;
 
+mod animation;
 mod gui;
+mod hdr;
+mod outputs;
 mod renderer;
+mod textures;
+mod y4m;
 
 pub struct Settings {
     pub target_framerate: Duration,
     pub mouse_wheel_step: f32,
+    /// Format of the displayed pass output
+    pub target_format: TargetFormat,
+    /// How the output colors are encoded, on the canvas and in exports
+    pub color_space: ColorSpace,
+    /// How float targets are displayed
+    pub display: DisplaySettings,
+    /// Number of frames between two read backs of the shader outputs
+    pub readback_interval: u32,
 }
 
 pub struct ExportData {
     pub size: Vector2<u32>,
     pub format: ImageFormat,
+    /// Bits per channel of images, one of those supported by the format
+    pub bit_depth: BitDepth,
     pub path: PathBuf,
+    /// Export an animation instead of a still image
+    pub animated: bool,
+    pub animation: AnimationSettings,
+    pub alpha: AlphaMode,
 }
 
 impl Default for ExportData {
@@ -42,11 +70,142 @@ impl Default for ExportData {
         Self {
             size: Vector2::from([2048, 2048]),
             format: ImageFormat::Png,
+            bit_depth: BitDepth::Eight,
             path: PathBuf::from_str("render.png").unwrap(),
+            animated: false,
+            animation: Default::default(),
+            alpha: AlphaMode::Straight,
         }
     }
 }
 
+/// How the alpha of the exported pixels is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Colors and alpha as written by the shader
+    Straight,
+    /// Colors are multiplied by alpha
+    Premultiplied,
+    /// Alpha is set to 1, like on the canvas
+    Opaque,
+}
+
+impl AlphaMode {
+    pub fn apply(&self, image: &mut RgbaImage) {
+        match self {
+            AlphaMode::Straight => {}
+            AlphaMode::Premultiplied => {
+                for pixel in image.pixels_mut() {
+                    let alpha = pixel[3] as u16;
+                    for channel in &mut pixel.0[..3] {
+                        *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+                    }
+                }
+            }
+            AlphaMode::Opaque => {
+                for pixel in image.pixels_mut() {
+                    pixel[3] = 255;
+                }
+            }
+        }
+    }
+
+    pub fn apply_float(&self, image: &mut FloatImage) {
+        match self {
+            AlphaMode::Straight => {}
+            AlphaMode::Premultiplied => {
+                for pixel in image.pixels_mut() {
+                    let alpha = pixel[3];
+                    for channel in &mut pixel.0[..3] {
+                        *channel *= alpha;
+                    }
+                }
+            }
+            AlphaMode::Opaque => {
+                for pixel in image.pixels_mut() {
+                    pixel[3] = 1.0;
+                }
+            }
+        }
+    }
+}
+
+/// Sound shader rendering configuration
+#[derive(Default)]
+pub struct SoundExportData {
+    /// A glsl file declaring mainSound
+    pub shader: Option<PathBuf>,
+    pub settings: SoundSettings,
+}
+
+/// Channel of the displayed pass assigned from the GUI
+#[derive(Default, Clone)]
+pub struct ChannelSlot {
+    /// Replaces the channel of the shader, unless it is bound to a pass
+    pub channel: Option<Channel>,
+    pub sampler: Option<ChannelSampler>,
+}
+
+/// Inputs of the displayed pass changed from the GUI, kept when the shader is reloaded
+#[derive(Default, Clone)]
+pub struct PassOverrides {
+    pub channels: [ChannelSlot; CHANNEL_COUNT],
+    /// How the previous frame is sampled
+    pub previous: Option<PreviousSampler>,
+    /// How the primitives of a vertex shader are drawn
+    pub vertex: Option<VertexSettings>,
+    /// Entry point of the displayed pass, kept while the module still has it
+    pub entry_point: Option<String>,
+}
+
+impl PassOverrides {
+    /// Apply the overrides to the displayed pass
+    fn apply(&self, passes: &mut [Pass]) {
+        if let Some(pass) = passes.last_mut() {
+            for (i, slot) in self.channels.iter().enumerate() {
+                match &slot.channel {
+                    // Channels bound to passes are fixed by the shader
+                    Some(channel) if !matches!(pass.channels[i], Channel::Pass { .. }) => {
+                        pass.channels[i] = channel.clone();
+                    }
+                    _ => {}
+                }
+                if let Some(sampler) = slot.sampler {
+                    pass.samplers[i] = sampler;
+                }
+            }
+            if let Some(previous) = self.previous {
+                pass.previous = previous;
+            }
+        }
+    }
+}
+
+/// Images that can be bound to a channel
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif"];
+/// Audio files that can be bound to a channel
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "flac"];
+
+/// The channel input for an image or an audio file
+fn channel_file(path: PathBuf) -> Channel {
+    match path.extension().and_then(|it| it.to_str()) {
+        Some(ext) if AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()) => {
+            Channel::Audio(path)
+        }
+        _ => Channel::Texture(path),
+    }
+}
+
+/// The target format a shader requires, None if it follows the settings.
+/// The output image of compute shaders is compiled with their format.
+fn required_target_format(shader: &Shader) -> Option<TargetFormat> {
+    match shader.metadata.as_ref().and_then(|it| it.target_format) {
+        Some(target_format) => Some(target_format),
+        None if shader.kind == ShaderKind::Compute => Some(TargetFormat::Surface),
+        None => None,
+    }
+}
+
 pub struct Nuance {
     /// The main window
     window: Window,
@@ -80,9 +239,20 @@ pub struct Nuance {
 
     /// Export configuration
     export_data: ExportData,
+    /// Sound export configuration
+    sound_data: SoundExportData,
+    /// Inputs assigned from the GUI
+    overrides: PassOverrides,
+    /// Values written by the shader
+    outputs: Outputs,
 
     ask_load: bool,
+    /// The channel to pick an image for
+    ask_channel: Option<usize>,
     ask_export: bool,
+    ask_outputs_export: Option<OutputsFormat>,
+    ask_sound_shader: bool,
+    ask_sound_export: bool,
 }
 
 impl Nuance {
@@ -118,6 +288,10 @@ impl Nuance {
             settings: Settings {
                 target_framerate: Duration::from_secs_f32(1.0 / 60.0),
                 mouse_wheel_step: 0.1,
+                target_format: TargetFormat::Surface,
+                color_space: ColorSpace::Srgb,
+                display: Default::default(),
+                readback_interval: 10,
             },
             shader: None,
             shader_loader: ShaderLoader::new(),
@@ -132,14 +306,26 @@ impl Nuance {
                 ratio: (canvas_size.width) as f32 / canvas_size.height as f32,
                 time: 0.0,
                 frame: 0,
+                time_delta: 0.0,
+                date: Vector4::from([0.0; 4]),
+                bass: 0.0,
+                mid: 0.0,
+                treble: 0.0,
             },
             last_draw: Instant::now(),
             sim_start: Instant::now(),
             sim_duration: Duration::from_nanos(0),
             paused: false,
             export_data: Default::default(),
+            sound_data: Default::default(),
+            overrides: Default::default(),
+            outputs: Default::default(),
             ask_load: false,
+            ask_channel: None,
             ask_export: false,
+            ask_outputs_export: None,
+            ask_sound_shader: false,
+            ask_sound_export: false,
         })
     }
 
@@ -156,8 +342,14 @@ impl Nuance {
                 let scale_factor = self.window.scale_factor();
                 let ui_width = self.gui.ui_width as f64 * scale_factor;
                 if position.x > ui_width {
-                    self.globals.mouse =
-                        Vector2::from([(position.x - ui_width) as u32, position.y as u32]);
+                    // The canvas is stretched to the available space
+                    let window_size = self.window.inner_size();
+                    let canvas_width = window_size.width as f64 - ui_width;
+                    let resolution = self.globals.resolution;
+                    self.globals.mouse = Vector2::from([
+                        ((position.x - ui_width) * resolution.x as f64 / canvas_width) as u32,
+                        (position.y * resolution.y as f64 / window_size.height as f64) as u32,
+                    ]);
                 }
             }
             WindowEvent::MouseWheel {
@@ -179,12 +371,14 @@ impl Nuance {
                 _ => {}
             },
             WindowEvent::Resized(size) => {
-                let mut size = size.into();
+                let size = size.into();
                 println!("Resizing {:?}", size);
                 self.renderer.resize(size);
-                size.x -= self.gui.ui_width;
-                self.renderer.resize_inner_canvas(size);
-                self.globals.resolution = size;
+                let size = self.canvas_size();
+                if size != self.globals.resolution {
+                    self.renderer.resize_inner_canvas(size);
+                    self.globals.resolution = size;
+                }
             }
             WindowEvent::CloseRequested => {
                 *control_flow = ControlFlow::Exit;
@@ -196,7 +390,7 @@ impl Nuance {
     /// Final update
     /// Called before draw and after handling all events
     pub fn update(&mut self, control_flow: &mut ControlFlow) {
-        // Poll the file watcher
+        // Poll the file watcher, any file of the shader may have changed
         if let Ok(DebouncedEvent::Write(_)) = self.watcher_rx.try_recv() {
             self.reload_shader();
         }
@@ -223,27 +417,80 @@ impl Nuance {
                 .add_filter("Shaders", ShaderLoader::supported_extensions())
                 .pick_file()
             {
-                self.unwatch();
                 self.load_shader(&path);
             }
             self.ask_load = false;
         }
 
-        if self.ask_export {
+        if let Some(channel) = self.ask_channel.take() {
             if let Some(path) = FileDialog::new()
                 .set_parent(&self.window)
-                .add_filter("Image", self.export_data.format.extensions_str())
-                .save_file()
+                .add_filter("Image", IMAGE_EXTENSIONS)
+                .add_filter("Audio", AUDIO_EXTENSIONS)
+                .pick_file()
             {
+                self.set_channel(channel, channel_file(path));
+            }
+        }
+
+        if self.ask_export {
+            let dialog = FileDialog::new().set_parent(&self.window);
+            let path = if !self.export_data.animated {
+                dialog
+                    .add_filter("Image", self.export_data.format.extensions_str())
+                    .save_file()
+            } else if let Some(extension) = self.export_data.animation.format.extension() {
+                dialog.add_filter("Animation", &[extension]).save_file()
+            } else {
+                // Frames are sent to a command, there is no file to pick
+                self.export_animation();
+                None
+            };
+            if let Some(path) = path {
                 self.export_data.path.push(&path);
-                self.export_image();
+                if self.export_data.animated {
+                    self.export_animation();
+                } else {
+                    self.export_image();
+                }
             }
             self.ask_export = false;
         }
 
+        if let Some(format) = self.ask_outputs_export.take() {
+            if let Some(path) = FileDialog::new()
+                .set_parent(&self.window)
+                .add_filter(format.extension(), &[format.extension()])
+                .save_file()
+            {
+                self.export_outputs(&path, format);
+            }
+        }
+
+        if self.ask_sound_shader {
+            if let Some(path) = FileDialog::new()
+                .set_parent(&self.window)
+                .add_filter("Sound shader", &["glsl", "frag"])
+                .pick_file()
+            {
+                self.sound_data.shader = Some(path);
+            }
+            self.ask_sound_shader = false;
+        }
+
+        if self.ask_sound_export {
+            if let Some(path) = FileDialog::new()
+                .set_parent(&self.window)
+                .add_filter("WAV", &["wav"])
+                .save_file()
+            {
+                self.export_sound(&path);
+            }
+            self.ask_sound_export = false;
+        }
+
         // Resize canvas if the UI got resized
-        let mut size: Vector2<u32> = self.window.inner_size().into();
-        size.x -= self.gui.ui_width;
+        let size = self.canvas_size();
         if size != self.globals.resolution {
             println!("Resizing canvas {:?}", size);
             self.renderer.resize_inner_canvas(size);
@@ -264,6 +511,14 @@ impl Nuance {
             scale_factor: self.window.scale_factor() as f32,
         };
 
+        if !self.is_paused() {
+            self.globals.time_delta = self.last_draw.elapsed().as_secs_f32();
+            self.globals.set_date(SystemTime::now());
+            // Audio channels follow the shader time
+            self.globals
+                .set_audio_levels(self.renderer.update_audio(self.globals.time));
+        }
+
         // Generate the GUI
         let (paint_jobs, _, textures_delta) = Gui::render(self, &screen_desc);
 
@@ -273,6 +528,16 @@ impl Nuance {
             self.window.request_redraw();
         }*/
 
+        // Read back the outputs of the rendered frame every few frames
+        let output_frame = !self.is_paused()
+            && self.shader_metadata().is_some_and(|it| it.output.is_some())
+            && self.globals.frame % self.settings.readback_interval.max(1) == 0
+            && !self.outputs.is_pending();
+        if output_frame {
+            self.outputs.request(self.globals.frame);
+            self.renderer.request_outputs();
+        }
+
         // Render the UI
         self.renderer
             .render(
@@ -287,6 +552,19 @@ impl Nuance {
             )
             .unwrap();
 
+        if self.outputs.is_pending() {
+            if let Some(bytes) = self.renderer.read_outputs() {
+                let block = self
+                    .shader
+                    .as_ref()
+                    .and_then(|it| it.metadata.as_ref())
+                    .and_then(|it| it.output.as_ref());
+                if let Some(block) = block {
+                    self.outputs.receive(block, &bytes);
+                }
+            }
+        }
+
         if !self.is_paused() {
             self.globals.frame += 1;
             self.last_draw = Instant::now();
@@ -304,18 +582,81 @@ impl Nuance {
         info!("Loading {}", path.as_ref().to_str().unwrap());
         let reload_start = Instant::now();
 
-        match self.shader_loader.load_shader(&path) {
-            Ok((shader, source)) => {
+        // Inputs assigned from the GUI are only kept for the same shader
+        let overrides = if self
+            .shader
+            .as_ref()
+            .is_some_and(|it| it.main == path.as_ref())
+        {
+            self.overrides.clone()
+        } else {
+            Default::default()
+        };
+
+        let loaded = self
+            .shader_loader
+            .load_shader(&path)
+            .and_then(|(mut shader, sources)| {
+                overrides.apply(&mut shader.passes);
+                if let (Some(vertex), Some(metadata)) = (overrides.vertex, shader.metadata.as_mut())
+                {
+                    metadata.vertex = vertex;
+                }
+                if let Some(entry_point) = overrides
+                    .entry_point
+                    .as_ref()
+                    .filter(|it| shader.entry_points.contains(it))
+                {
+                    shader.passes.last_mut().unwrap().entry_point = entry_point.clone();
+                }
                 let buffer_size = if let Some(metadata) = shader.metadata.as_ref() {
                     metadata.params_buffer_size()
                 } else {
                     0
                 };
+                let output_size = shader
+                    .metadata
+                    .as_ref()
+                    .and_then(|it| it.output.as_ref())
+                    .map_or(0, |it| it.size);
 
-                self.renderer
-                    .set_shader(source, Globals::std430_size_static() as u32, buffer_size);
+                // The shader may require a target format, the setting is kept for the next ones
+                let target_format =
+                    required_target_format(&shader).unwrap_or(self.settings.target_format);
 
+                self.renderer.set_shader(
+                    sources,
+                    &shader.passes,
+                    (
+                        shader.kind,
+                        target_format,
+                        shader
+                            .metadata
+                            .as_ref()
+                            .map(|it| it.vertex)
+                            .unwrap_or_default(),
+                    ),
+                    Globals::std430_size_static() as u32,
+                    (buffer_size, output_size),
+                )?;
+                Ok(shader)
+            });
+        match loaded {
+            Ok(shader) => {
+                // The new shader may use other files
+                if self.watching {
+                    self.unwatch();
+                }
+                if let Some(framerate) = shader.framerate {
+                    self.settings.target_framerate = Duration::from_secs_f32(1.0 / framerate);
+                }
                 self.shader = Some(shader);
+                self.overrides = overrides;
+                self.outputs.clear();
+                self.gui.clear_thumbnails();
+                if self.watching {
+                    self.watch();
+                }
                 // Reset the running globals
                 self.globals.reset();
                 self.sim_start = Instant::now();
@@ -327,45 +668,179 @@ impl Nuance {
                 );
             }
             Err(e) => {
-                error!("{}", e);
+                error!("{:#}", e);
                 error!("Can't load {}", path.as_ref().to_str().unwrap());
             }
         }
     }
 
+    /// Switch to another entry point of the loaded shader, this does not reload the file
+    fn set_entry_point(&mut self, entry_point: String) {
+        let buffer_size = self
+            .shader_metadata()
+            .map(|it| it.params_buffer_size())
+            .unwrap_or(0);
+        if let Some(shader) = self.shader.as_mut() {
+            info!("Switching to entry point {}", entry_point);
+            self.renderer.set_entry_point(
+                &entry_point,
+                Globals::std430_size_static() as u32,
+                buffer_size,
+            );
+            shader.passes.last_mut().unwrap().entry_point = entry_point.clone();
+            self.overrides.entry_point = Some(entry_point);
+        }
+    }
+
+    /// Change the format the displayed pass renders to
+    fn set_target_format(&mut self, target_format: TargetFormat) {
+        let buffer_size = self
+            .shader_metadata()
+            .map(|it| it.params_buffer_size())
+            .unwrap_or(0);
+        info!("Switching to target format {:?}", target_format);
+        self.settings.target_format = target_format;
+        self.renderer.set_target_format(
+            target_format,
+            Globals::std430_size_static() as u32,
+            buffer_size,
+        );
+    }
+
+    /// Change how the output colors are encoded
+    fn set_color_space(&mut self, color_space: ColorSpace) {
+        let buffer_size = self
+            .shader_metadata()
+            .map(|it| it.params_buffer_size())
+            .unwrap_or(0);
+        info!("Switching to color space {:?}", color_space);
+        self.settings.color_space = color_space;
+        self.renderer.set_color_space(
+            color_space,
+            Globals::std430_size_static() as u32,
+            buffer_size,
+        );
+    }
+
+    /// Change how the primitives of the vertex shader are drawn
+    fn set_vertex_settings(&mut self, vertex: VertexSettings) {
+        let buffer_size = self
+            .shader_metadata()
+            .map(|it| it.params_buffer_size())
+            .unwrap_or(0);
+        if let Some(metadata) = self.shader_metadata_mut() {
+            metadata.vertex = vertex;
+        }
+        self.overrides.vertex = Some(vertex);
+        self.renderer.set_vertex_settings(
+            vertex,
+            Globals::std430_size_static() as u32,
+            buffer_size,
+        );
+    }
+
+    /// Change how float targets are displayed
+    fn set_display_settings(&mut self, display: DisplaySettings) {
+        self.settings.display = display;
+        self.renderer.set_display_settings(display);
+    }
+
+    /// This shows a file dialog to pick the image of a channel
+    /// This only happens next frame
+    fn ask_channel_image(&mut self, channel: usize) {
+        self.ask_channel = Some(channel);
+    }
+
+    /// Bind a channel of the displayed pass
+    fn set_channel(&mut self, channel: usize, input: Channel) {
+        let mut overrides = self.overrides.clone();
+        overrides.channels[channel].channel = Some(input);
+        self.set_overrides(overrides);
+    }
+
+    /// Change how a channel of the displayed pass is sampled
+    fn set_channel_sampler(&mut self, channel: usize, sampler: ChannelSampler) {
+        let mut overrides = self.overrides.clone();
+        overrides.channels[channel].sampler = Some(sampler);
+        self.set_overrides(overrides);
+    }
+
+    /// Change how the previous frame of the displayed pass is sampled
+    fn set_previous_sampler(&mut self, previous: PreviousSampler) {
+        let mut overrides = self.overrides.clone();
+        overrides.previous = Some(previous);
+        self.set_overrides(overrides);
+    }
+
+    /// Rebind the inputs, the overrides are kept only if the images can be loaded
+    fn set_overrides(&mut self, overrides: PassOverrides) {
+        let buffer_size = self
+            .shader_metadata()
+            .map(|it| it.params_buffer_size())
+            .unwrap_or(0);
+        if let Some(shader) = self.shader.as_mut() {
+            let mut passes = shader.passes.clone();
+            overrides.apply(&mut passes);
+            match self.renderer.set_inputs(
+                &passes,
+                Globals::std430_size_static() as u32,
+                buffer_size,
+            ) {
+                Ok(()) => {
+                    shader.passes = passes;
+                    self.overrides = overrides;
+                }
+                Err(e) => error!("{:#}", e),
+            }
+        }
+    }
+
     fn reload_shader(&mut self) {
         info!("Reloading !");
         let path = self.shader.as_ref().unwrap().main.clone();
         self.load_shader(&path);
     }
 
-    /// Watch the currently loaded file
+    /// Watch all the files of the currently loaded shader
     fn watch(&mut self) {
-        // TODO should watch for every file that is part of compilation
-        if let Some(path) = self.shader.as_ref().map(|it| &it.main) {
-            self.watcher
-                .watch(path, RecursiveMode::NonRecursive)
-                .unwrap();
+        if let Some(shader) = self.shader.as_ref() {
+            for path in shader.sources.iter() {
+                if let Err(e) = self.watcher.watch(path, RecursiveMode::NonRecursive) {
+                    error!("Can't watch {}, cause : {:?}", path.display(), e);
+                }
+            }
             info!("Watching loaded shader for changes.");
         }
     }
 
     /// Immediate unwatch
     fn unwatch(&mut self) {
-        // TODO should unwatch for every file that is part of compilation
         if let Some(shader) = self.shader.as_ref() {
-            match self.watcher.unwatch(&shader.main) {
-                Ok(_) => {
-                    info!("Not watching for changes anymore.");
+            for path in shader.sources.iter() {
+                match self.watcher.unwatch(path) {
+                    Ok(_) => {}
+                    Err(e) => match e {
+                        Error::WatchNotFound => {
+                            info!("Was not watching {} ?", path.display());
+                        }
+                        other => {
+                            error!("Can't unwatch, cause : {:?}", other);
+                        }
+                    },
                 }
-                Err(e) => match e {
-                    Error::WatchNotFound => {
-                        info!("Was not watching ?");
-                    }
-                    other => {
-                        error!("Can't unwatch, cause : {:?}", other);
-                    }
-                },
+            }
+            info!("Not watching for changes anymore.");
+        }
+    }
+
+    /// Size of the canvas, fixed by the shader or following the window size
+    fn canvas_size(&self) -> Vector2<u32> {
+        match self.shader.as_ref().and_then(|it| it.resolution) {
+            Some(resolution) => resolution,
+            None => {
+                let mut size: Vector2<u32> = self.window.inner_size().into();
+                size.x -= self.gui.ui_width;
+                size
             }
         }
     }
@@ -393,35 +868,213 @@ impl Nuance {
         let export_start = Instant::now();
 
         let ExportData {
-            size, path, format, ..
+            size,
+            path,
+            format,
+            bit_depth,
+            alpha,
+            ..
         } = &self.export_data;
 
         let mut globals = self.globals.clone();
         globals.resolution = *size;
         globals.ratio = globals.resolution.x as f32 / globals.resolution.y as f32;
+        let params_buffer = self
+            .shader_metadata()
+            .map(|it| it.params_buffer())
+            .unwrap_or_default();
 
-        self.renderer
-            .render_to_buffer(
-                *size,
-                &self
-                    .shader_metadata()
-                    .map(|it| it.params_buffer())
-                    .unwrap_or_default(),
-                globals.as_std430().as_bytes(),
-                |buf| {
-                    let image = ImageBuffer::<Rgba<_>, _>::from_raw(size.x, size.y, &buf[..])
-                        .context("Can't create image from buffer")?;
-                    image.save_with_format(path, *format)?;
+        if *bit_depth != BitDepth::Eight {
+            self.export_float_image(&params_buffer, globals.as_std430().as_bytes());
+            return;
+        }
 
-                    Ok(())
-                },
-            )
-            .unwrap();
+        let exported = self.renderer.render_to_buffer(
+            *size,
+            &params_buffer,
+            globals.as_std430().as_bytes(),
+            |pixels| {
+                let mut image = RgbaImage::from_raw(size.x, size.y, pixels)
+                    .context("Can't create image from buffer")?;
+                if self.shader_metadata().is_some_and(|it| it.shadertoy) {
+                    // Shadertoy shaders are rendered upside down
+                    imageops::flip_vertical_in_place(&mut image);
+                }
+                alpha.apply(&mut image);
+                match format {
+                    // These formats have no alpha channel, premultiplied colors are over black
+                    ImageFormat::Jpeg | ImageFormat::Bmp => DynamicImage::ImageRgba8(image)
+                        .to_rgb8()
+                        .save_with_format(path, *format)?,
+                    _ => image.save_with_format(path, *format)?,
+                }
 
-        info!(
-            "Exported image ! (took {} ms)",
-            export_start.elapsed().as_millis()
+                Ok(())
+            },
         );
+
+        match exported {
+            Ok(()) => info!(
+                "Exported image ! (took {} ms)",
+                export_start.elapsed().as_millis()
+            ),
+            Err(e) => error!("Can't export image : {:#}", e),
+        }
+    }
+
+    /// Export the float output of the displayed pass to a 16 bits or OpenEXR image
+    fn export_float_image(&self, params_buffer: &[u8], push_constants: &[u8]) {
+        let export_start = Instant::now();
+
+        let ExportData {
+            size,
+            path,
+            format,
+            bit_depth,
+            alpha,
+            ..
+        } = &self.export_data;
+
+        let exported =
+            self.renderer
+                .render_to_float_buffer(*size, params_buffer, push_constants, |pixels| {
+                    let mut image = FloatImage::from_raw(size.x, size.y, pixels)
+                        .context("Can't create image from buffer")?;
+                    if self.shader_metadata().is_some_and(|it| it.shadertoy) {
+                        // Shadertoy shaders are rendered upside down
+                        imageops::flip_vertical_in_place(&mut image);
+                    }
+                    alpha.apply_float(&mut image);
+                    if *format == ImageFormat::OpenExr {
+                        hdr::save_exr(&image, path, *bit_depth)
+                    } else {
+                        hdr::save_16(&image, path, *format, self.settings.color_space)
+                    }
+                });
+
+        match exported {
+            Ok(()) => info!(
+                "Exported {} image ! (took {} ms)",
+                bit_depth.label(*format),
+                export_start.elapsed().as_millis()
+            ),
+            Err(e) => error!("Can't export image : {:#}", e),
+        }
+    }
+
+    /// Render frames with a fixed timestep from the start time of the animation
+    fn export_animation(&self) {
+        let export_start = Instant::now();
+
+        let ExportData {
+            size,
+            path,
+            animation,
+            alpha,
+            ..
+        } = &self.export_data;
+        let frame_count = animation.frame_count();
+        let sliders = self
+            .shader_metadata()
+            .map(|it| it.sliders.as_slice())
+            .unwrap_or_default();
+        // Shadertoy shaders are rendered upside down
+        let flip = self.shader_metadata().is_some_and(|it| it.shadertoy);
+
+        // The mouse keeps its position at the start of the export
+        let mut globals = self.globals.clone();
+        globals.resolution = *size;
+        globals.ratio = globals.resolution.x as f32 / globals.resolution.y as f32;
+
+        let exported =
+            AnimationWriter::create(path, *size, animation, frame_count).and_then(|mut writer| {
+                let rendered = self.renderer.render_sequence(
+                    *size,
+                    &animation.clock(),
+                    &globals,
+                    sliders,
+                    |_, globals| {
+                        globals.set_audio_levels(self.renderer.update_audio(globals.time));
+                    },
+                    |_, pixels| {
+                        let mut image = RgbaImage::from_raw(size.x, size.y, pixels)
+                            .context("Can't create image from buffer")?;
+                        if flip {
+                            imageops::flip_vertical_in_place(&mut image);
+                        }
+                        alpha.apply(&mut image);
+                        writer.write_frame(image)
+                    },
+                );
+                // The writer is finished even if a frame failed so a command is waited for
+                let finished = writer.finish();
+                rendered.and(finished)
+            });
+
+        match exported {
+            Ok(()) => info!(
+                "Exported animation ! ({} frames, took {} ms)",
+                frame_count,
+                export_start.elapsed().as_millis()
+            ),
+            Err(e) => error!("Can't export animation : {:#}", e),
+        }
+    }
+
+    /// This shows a file dialog to export the outputs
+    /// This only happens next frame
+    fn ask_to_export_outputs(&mut self, format: OutputsFormat) {
+        self.ask_outputs_export = Some(format);
+    }
+
+    fn export_outputs(&self, path: &Path, format: OutputsFormat) {
+        if let Some(block) = self.shader_metadata().and_then(|it| it.output.as_ref()) {
+            let content = match format {
+                OutputsFormat::Csv => self.outputs.to_csv(block),
+                OutputsFormat::Json => {
+                    serde_json::to_string_pretty(&self.outputs.to_json(block)).unwrap()
+                }
+            };
+            match std::fs::write(path, content) {
+                Ok(()) => info!("Exported outputs to {}", path.display()),
+                Err(e) => error!("Can't export outputs : {}", e),
+            }
+        }
+    }
+
+    /// This shows a file dialog to pick the sound shader
+    /// This only happens next frame
+    fn ask_sound_shader(&mut self) {
+        self.ask_sound_shader = true;
+    }
+
+    /// This shows a file dialog to export the sound
+    /// This only happens next frame
+    fn ask_to_export_sound(&mut self) {
+        self.ask_sound_export = true;
+    }
+
+    fn export_sound(&mut self, path: &Path) {
+        let export_start = Instant::now();
+
+        let SoundExportData { shader, settings } = &self.sound_data;
+        let shader = match shader {
+            Some(shader) => shader,
+            None => return,
+        };
+        let exported = self
+            .shader_loader
+            .load_sound(shader)
+            .and_then(|source| self.renderer.render_sound(source, *settings))
+            .and_then(|samples| sound::write_wav(path, &samples, settings.sample_rate));
+        match exported {
+            Ok(()) => info!(
+                "Exported sound to {} ! (took {} ms)",
+                path.display(),
+                export_start.elapsed().as_millis()
+            ),
+            Err(e) => error!("Can't export sound : {:#}", e),
+        }
     }
 
     fn pause(&mut self) {
@@ -442,6 +1095,14 @@ impl Nuance {
         self.shader.is_some()
     }
 
+    /// Format the displayed pass renders to, the one required by the shader or the setting
+    fn target_format(&self) -> TargetFormat {
+        self.shader
+            .as_ref()
+            .and_then(required_target_format)
+            .unwrap_or(self.settings.target_format)
+    }
+
     fn shader_metadata(&self) -> Option<&ShaderMetadata> {
         self.shader
             .as_ref()
@@ -0,0 +1,163 @@
+//! Values read back from the output buffer of the displayed pass
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use serde_json::{json, Map, Value};
+
+use nuance::{OutputBlock, OutputField};
+
+/// Number of read backs kept for plots and exports
+const HISTORY_LENGTH: usize = 256;
+
+/// Names of the components of vector fields
+pub const COMPONENTS: [&str; 4] = ["x", "y", "z", "w"];
+
+/// File formats the outputs can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputsFormat {
+    Csv,
+    Json,
+}
+
+impl OutputsFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputsFormat::Csv => "csv",
+            OutputsFormat::Json => "json",
+        }
+    }
+}
+
+/// The values of each field read back at a frame
+pub struct Readback {
+    pub frame: u32,
+    pub values: Vec<Vec<f64>>,
+}
+
+#[derive(Default)]
+pub struct Outputs {
+    /// Frame of the pending read back
+    pending: Option<u32>,
+    history: VecDeque<Readback>,
+}
+
+impl Outputs {
+    /// Remember the frame a read back was requested at
+    pub fn request(&mut self, frame: u32) {
+        self.pending = Some(frame);
+    }
+
+    /// Whether a requested read back hasn't been received yet
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Decode the content of the buffer for the pending read back
+    pub fn receive(&mut self, block: &OutputBlock, bytes: &[u8]) {
+        if let Some(frame) = self.pending.take() {
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            self.history.push_back(Readback {
+                frame,
+                values: block.read(bytes),
+            });
+        }
+    }
+
+    pub fn latest(&self) -> Option<&Readback> {
+        self.history.back()
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &Readback> {
+        self.history.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.pending = None;
+        self.history.clear();
+    }
+
+    /// One row per read back, one column per value
+    pub fn to_csv(&self, block: &OutputBlock) -> String {
+        let mut csv = String::from("frame");
+        for field in &block.fields {
+            for column in value_names(field) {
+                write!(csv, ",{}", column).unwrap();
+            }
+        }
+        csv.push('\n');
+        for readback in &self.history {
+            write!(csv, "{}", readback.frame).unwrap();
+            for value in readback.values.iter().flatten() {
+                write!(csv, ",{}", value).unwrap();
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// An array of read backs, vectors and arrays are kept as nested arrays
+    pub fn to_json(&self, block: &OutputBlock) -> Value {
+        Value::Array(
+            self.history
+                .iter()
+                .map(|readback| {
+                    let fields: Map<String, Value> = block
+                        .fields
+                        .iter()
+                        .zip(&readback.values)
+                        .map(|(field, values)| (field.name.clone(), field_json(field, values)))
+                        .collect();
+                    json!({ "frame": readback.frame, "values": fields })
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Name of each value of a field, like `probe.x` or `histogram[3]`
+pub fn value_names(field: &OutputField) -> Vec<String> {
+    let elements: Vec<String> = match field.length {
+        Some(length) => (0..length)
+            .map(|i| format!("{}[{}]", field.name, i))
+            .collect(),
+        None => vec![field.name.clone()],
+    };
+    elements
+        .iter()
+        .flat_map(|element| {
+            (0..field.components as usize).map(move |c| {
+                if field.components == 1 {
+                    element.clone()
+                } else {
+                    format!("{}.{}", element, COMPONENTS[c])
+                }
+            })
+        })
+        .collect()
+}
+
+fn field_json(field: &OutputField, values: &[f64]) -> Value {
+    let element = |values: &[f64]| {
+        if field.components == 1 {
+            json!(values[0])
+        } else {
+            json!(values)
+        }
+    };
+    match field.length {
+        Some(_) => Value::Array(
+            values
+                .chunks(field.components as usize)
+                .map(element)
+                .collect(),
+        ),
+        None => element(values),
+    }
+}
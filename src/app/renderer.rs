@@ -1,16 +1,124 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::future::Future;
 use std::mem;
 use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll, Waker};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use crevice::std430::{AsStd430, Std430};
 use egui::{ClippedMesh, TextureId, TexturesDelta};
 use egui_wgpu_backend::ScreenDescriptor;
-use log::{debug, error, info};
+use half::f16;
+use log::{debug, error, info, warn};
 use mint::Vector2;
 use puffin::ProfilerScope;
+use shaderc::ShaderKind;
 use wgpu::*;
 use winit::window::Window;
 
-use nuance::renderer::ShaderRenderPass;
+use nuance::audio::{Audio, AUDIO_TEXTURE_WIDTH};
+use nuance::offline::FrameClock;
+use nuance::renderer::{
+    self, ComputePass, DisplayPass, DisplaySettings, ShaderRenderPass, SoundPass, VertexPass,
+};
+use nuance::sound::{SoundSettings, SOUND_BLOCK_SIZE};
+use nuance::{
+    Channel, ChannelSampler, ColorSpace, Filter, Globals, Pass, PreviousSampler, Slider,
+    TargetFormat, TextureFile, VertexSettings, Wrap,
+};
+
+use crate::app::textures;
+
+/// Format of the passes not displayed, Shadertoy buffers are half floats too
+const PASS_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Format high bit depth exports are rendered to when the canvas target isn't float
+const FLOAT_EXPORT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Number of frames the passes not displayed are rendered before an export at another size than
+/// the canvas, to build their previous outputs
+const WARM_UP_FRAMES: u32 = 64;
+
+/// Output textures of a pass rendered offscreen
+struct PassTarget {
+    tex: Texture,
+    last_tex: Texture,
+    last_tex_bg: BindGroup,
+}
+
+impl PassTarget {
+    fn new(device: &Device, size: Vector2<u32>, previous: PreviousSampler) -> Self {
+        let tex = Renderer::create_render_tex(device, size, PASS_FORMAT);
+        let last_tex = Renderer::create_last_render_tex(device, size, PASS_FORMAT);
+        let last_tex_bg = Renderer::create_last_render_tex_bg(device, &last_tex, previous);
+        Self {
+            tex,
+            last_tex,
+            last_tex_bg,
+        }
+    }
+}
+
+/// Textures of a render at another size than the canvas, the displayed output is copied to a
+/// buffer to be read back
+struct Offscreen {
+    size: Vector2<u32>,
+    render_tex: Texture,
+    /// Previous frame of the displayed pass, the canvas one is sampled if None
+    last_render_tex: Option<Texture>,
+    last_render_tex_bg: BindGroup,
+    display_tex: Option<Texture>,
+    msaa_tex: Option<Texture>,
+    pass_targets: Vec<PassTarget>,
+    channels_bgs: Vec<BindGroup>,
+    compute_output_bg: Option<BindGroup>,
+    /// Pipelines of the displayed pass for a float output, when the canvas target isn't float
+    rpass: Option<ShaderRenderPass>,
+    vertex_pass: Option<VertexPass>,
+    /// Format of the texture read back, the display one if any
+    output_format: TextureFormat,
+    /// Rows are padded to the copy alignment
+    output_buffer: Buffer,
+    padded_bytes_per_row: u32,
+}
+
+/// Mapping of a buffer in progress
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), BufferAsyncError>> + Send>>;
+
+/// Storage buffer written by the displayed pass and the buffer it is copied to for reading
+struct OutputBuffers {
+    buffer: Buffer,
+    staging: Buffer,
+    size: u64,
+    /// Copy the buffer to the staging buffer with the next frame
+    requested: bool,
+    /// The staging buffer is being mapped
+    mapping: Option<MapFuture>,
+}
+
+impl OutputBuffers {
+    fn new(device: &Device, size: u64) -> Self {
+        Self {
+            buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("output buffer"),
+                size,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            staging: device.create_buffer(&BufferDescriptor {
+                label: Some("output staging buffer"),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            size,
+            requested: false,
+            mapping: None,
+        }
+    }
+}
 
 pub struct Renderer {
     #[allow(dead_code)]
@@ -24,14 +132,54 @@ pub struct Renderer {
     surface: Surface,
     format: TextureFormat,
     render_size: Vector2<u32>,
+    /// Fragment, compute or vertex shader
+    kind: ShaderKind,
+    /// How the primitives of vertex shaders are drawn
+    vertex: VertexSettings,
+    /// Format of the displayed pass output
+    target_format: TargetFormat,
+    /// How the output colors are encoded
+    color_space: ColorSpace,
 
     render_tex: Texture,
     last_render_tex: Texture,
-    last_render_tex_bgl: BindGroupLayout,
     last_render_tex_bg: BindGroup,
+    /// Converts float targets to the surface format
+    display_pass: DisplayPass,
+    /// Surface format texture shown on the canvas when the target is a float texture
+    display_target: Option<(Texture, BindGroup)>,
+    display_settings: DisplaySettings,
+
+    channels_bgl: BindGroupLayout,
+    /// Texture bound to empty channels
+    empty_channel_tex: Texture,
+    /// Samplers of the channels, by settings
+    channel_samplers: HashMap<ChannelSampler, Sampler>,
+    /// Sampler of the textures declared in the shaders
+    texture_sampler: Sampler,
+    /// Image files bound to channels or declared by the passes
+    textures: HashMap<TextureFile, Texture>,
+    /// Audio files bound to channels, with the texture of their analysis
+    audio: HashMap<PathBuf, (Audio, Texture)>,
 
-    shader_module: Option<ShaderModule>,
-    shader_rpass: Option<ShaderRenderPass>,
+    /// A shader module for each pass
+    shader_modules: Vec<ShaderModule>,
+    passes: Vec<Pass>,
+    shader_rpasses: Vec<ShaderRenderPass>,
+    /// Compute shaders have a single pass writing to the render texture
+    compute_pass: Option<(ComputePass, BindGroup)>,
+    /// Vertex shaders have a single pass drawing to the render texture
+    vertex_pass: Option<VertexPass>,
+    /// Multisampled texture vertex shaders draw to, resolved to the render texture
+    msaa_tex: Option<Texture>,
+    /// Values written by the displayed pass
+    outputs: Option<OutputBuffers>,
+    /// Outputs of the passes before the displayed one
+    pass_targets: Vec<PassTarget>,
+    /// The channels bind group of each pass
+    channels_bgs: Vec<BindGroup>,
+    /// The textures bind group of each pass, with its layout
+    textures_bgs: Vec<(BindGroupLayout, BindGroup)>,
     pub egui_rpass: egui_wgpu_backend::RenderPass,
 }
 
@@ -87,9 +235,10 @@ impl Renderer {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("device_request"),
-                    features: Features::PUSH_CONSTANTS,
+                    features: Features::PUSH_CONSTANTS | Features::CLEAR_COMMANDS,
                     limits: Limits {
-                        max_push_constant_size: push_constants_size,
+                        max_push_constant_size: push_constants_size
+                            .max(renderer::vertex_push_constants_size()),
                         ..Default::default()
                     },
                 },
@@ -114,8 +263,24 @@ impl Renderer {
 
         let render_tex = Self::create_render_tex(&device, render_size, format);
 
-        let (last_render_tex, last_render_tex_bgl, last_render_tex_bg) =
-            Self::create_last_render_tex(&device, render_size, format);
+        let last_render_tex = Self::create_last_render_tex(&device, render_size, format);
+        let last_render_tex_bg =
+            Self::create_last_render_tex_bg(&device, &last_render_tex, Default::default());
+
+        let display_pass = DisplayPass::new(&device, format);
+
+        let channels_bgl = renderer::create_channels_bind_group_layout(&device);
+        let empty_channel_tex = Self::create_empty_channel_tex(&device);
+        let texture_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("texture sampler"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
 
         // The egui renderer in its own render pass
         let mut egui_rpass = egui_wgpu_backend::RenderPass::new(&device, format, 1);
@@ -143,37 +308,229 @@ impl Renderer {
             surface,
             format,
             render_size,
+            kind: ShaderKind::Fragment,
+            vertex: Default::default(),
+            target_format: TargetFormat::Surface,
+            color_space: ColorSpace::Srgb,
             render_tex,
             last_render_tex,
-            last_render_tex_bgl,
             last_render_tex_bg,
+            display_pass,
+            display_target: None,
+            display_settings: Default::default(),
+            channels_bgl,
+            empty_channel_tex,
+            channel_samplers: HashMap::new(),
+            texture_sampler,
+            textures: HashMap::new(),
+            audio: HashMap::new(),
 
             // Start with nothing loaded
-            shader_module: None,
-            shader_rpass: None,
+            shader_modules: Vec::new(),
+            passes: Vec::new(),
+            shader_rpasses: Vec::new(),
+            compute_pass: None,
+            vertex_pass: None,
+            msaa_tex: None,
+            outputs: None,
+            pass_targets: Vec::new(),
+            channels_bgs: Vec::new(),
+            textures_bgs: Vec::new(),
             egui_rpass,
         })
     }
 
+    /// Set the shader to render, with a compiled source for each pass
     pub fn set_shader(
         &mut self,
-        shader_source: ShaderSource,
+        shader_sources: Vec<ShaderSource>,
+        passes: &[Pass],
+        (kind, target_format, vertex): (ShaderKind, TargetFormat, VertexSettings),
+        push_constant_size: u32,
+        (params_buffer_size, output_buffer_size): (u64, u64),
+    ) -> Result<()> {
+        // Images are reloaded as they may have changed
+        let mut textures = HashMap::new();
+        Self::load_textures(&self.device, &self.queue, passes, &mut textures)?;
+        self.textures = textures;
+        let mut audio = HashMap::new();
+        Self::load_audio(&self.device, passes, &mut audio)?;
+        self.audio = audio;
+        self.create_channel_samplers(passes);
+
+        self.shader_modules = shader_sources
+            .into_iter()
+            .map(|source| {
+                self.device.create_shader_module(&ShaderModuleDescriptor {
+                    label: Some("nuance shader"),
+                    source,
+                })
+            })
+            .collect();
+        self.passes = passes.to_vec();
+        self.kind = kind;
+        self.target_format = target_format;
+        self.vertex = vertex;
+        // The previous pipelines don't match the new targets
+        self.compute_pass = None;
+        self.vertex_pass = None;
+        self.outputs =
+            (output_buffer_size > 0).then(|| OutputBuffers::new(&self.device, output_buffer_size));
+        self.textures_bgs = self.create_textures_bind_groups();
+        self.create_render_targets();
+        self.create_rpasses(push_constant_size, params_buffer_size);
+        self.warn_unfilterable_channels();
+        Ok(())
+    }
+
+    /// Rebind the inputs of the passes, only their channels and samplers may have changed
+    pub fn set_inputs(
+        &mut self,
+        passes: &[Pass],
+        push_constant_size: u32,
+        params_buffer_size: u64,
+    ) -> Result<()> {
+        Self::load_textures(&self.device, &self.queue, passes, &mut self.textures)?;
+        Self::load_audio(&self.device, passes, &mut self.audio)?;
+        self.create_channel_samplers(passes);
+        let previous_changed = self
+            .passes
+            .iter()
+            .zip(passes.iter())
+            .any(|(pass, new)| pass.previous != new.previous);
+        for (pass, new) in self.passes.iter_mut().zip(passes.iter()) {
+            pass.channels = new.channels.clone();
+            pass.samplers = new.samplers;
+            pass.previous = new.previous;
+        }
+        self.channels_bgs = self.create_channels_bind_groups(
+            &self.pass_targets,
+            &self.render_tex,
+            &self.last_render_tex,
+        );
+        if previous_changed {
+            // The previous textures are kept, only their bind groups and layouts change
+            self.create_last_render_tex_bgs();
+            self.create_rpasses(push_constant_size, params_buffer_size);
+        }
+        self.warn_unfilterable_channels();
+        Ok(())
+    }
+
+    /// Rebuild the pipelines with another entry point of the displayed pass module
+    pub fn set_entry_point(
+        &mut self,
+        entry_point: &str,
         push_constant_size: u32,
         params_buffer_size: u64,
     ) {
-        let module = self.device.create_shader_module(&ShaderModuleDescriptor {
-            label: Some("nuance fragment shader"),
-            source: shader_source,
-        });
-        self.shader_rpass = Some(ShaderRenderPass::new(
+        if let Some(pass) = self.passes.last_mut() {
+            pass.entry_point = entry_point.to_string();
+            self.create_rpasses(push_constant_size, params_buffer_size);
+        }
+    }
+
+    /// Create the pipeline of each pass, only the displayed pass has params
+    fn create_rpasses(&mut self, push_constant_size: u32, params_buffer_size: u64) {
+        if self.kind == ShaderKind::Compute {
+            self.shader_rpasses = Vec::new();
+            let compute_pass = ComputePass::new(
+                &self.device,
+                &self.shader_modules[0],
+                &self.passes[0].entry_point,
+                self.displayed_previous().filter == Filter::Linear,
+                &self.channels_bgl,
+                &self.textures_bgs[0].0,
+                push_constant_size,
+                params_buffer_size,
+                self.outputs.as_ref().map(|it| &it.buffer),
+                self.target_texture_format(),
+            );
+            let output_bg = self.create_compute_output_bg(&compute_pass, &self.render_tex);
+            self.compute_pass = Some((compute_pass, output_bg));
+            return;
+        }
+        self.compute_pass = None;
+        if self.kind == ShaderKind::Vertex {
+            self.shader_rpasses = Vec::new();
+            self.vertex_pass = Some(self.create_vertex_pass(
+                self.target_texture_format(),
+                push_constant_size,
+                params_buffer_size,
+            ));
+            return;
+        }
+        self.vertex_pass = None;
+        let last = self.passes.len().saturating_sub(1);
+        self.shader_rpasses = self
+            .shader_modules
+            .iter()
+            .zip(self.passes.iter().zip(self.textures_bgs.iter()))
+            .enumerate()
+            .map(|(i, (module, (pass, (textures_bgl, _))))| {
+                if i == last {
+                    return self.create_displayed_rpass(
+                        self.target_texture_format(),
+                        push_constant_size,
+                        params_buffer_size,
+                    );
+                }
+                ShaderRenderPass::new(
+                    &self.device,
+                    module,
+                    &pass.entry_point,
+                    &Self::create_last_render_tex_bgl(&self.device, pass.previous),
+                    &self.channels_bgl,
+                    textures_bgl,
+                    push_constant_size,
+                    0,
+                    None,
+                    PASS_FORMAT,
+                )
+            })
+            .collect();
+    }
+
+    /// Create the pipeline of the displayed pass of a fragment shader, rendering to `format`
+    fn create_displayed_rpass(
+        &self,
+        format: TextureFormat,
+        push_constant_size: u32,
+        params_buffer_size: u64,
+    ) -> ShaderRenderPass {
+        let last = self.passes.len() - 1;
+        ShaderRenderPass::new(
             &self.device,
-            &module,
-            &self.last_render_tex_bgl,
+            &self.shader_modules[last],
+            &self.passes[last].entry_point,
+            &Self::create_last_render_tex_bgl(&self.device, self.displayed_previous()),
+            &self.channels_bgl,
+            &self.textures_bgs[last].0,
             push_constant_size,
             params_buffer_size,
-            self.format,
-        ));
-        self.shader_module = Some(module);
+            self.outputs.as_ref().map(|it| &it.buffer),
+            format,
+        )
+    }
+
+    /// Create the pipeline of a vertex shader, drawing to `format`
+    fn create_vertex_pass(
+        &self,
+        format: TextureFormat,
+        push_constant_size: u32,
+        params_buffer_size: u64,
+    ) -> VertexPass {
+        VertexPass::new(
+            &self.device,
+            (&self.shader_modules[0], &self.shader_modules[1]),
+            &Self::create_last_render_tex_bgl(&self.device, self.displayed_previous()),
+            &self.channels_bgl,
+            &self.textures_bgs[0].0,
+            push_constant_size,
+            params_buffer_size,
+            self.vertex,
+            format,
+        )
     }
 
     pub fn render(
@@ -205,14 +562,61 @@ impl Renderer {
         mem::drop(_profiler_scope);
 
         if should_render {
-            if let Some(shader_rpass) = self.shader_rpass.as_ref() {
-                puffin::profile_scope!("shader render pass");
-                shader_rpass.update_buffers(&self.queue, params_buffer);
-                shader_rpass.execute(
+            puffin::profile_scope!("shader render pass");
+            if let Some(outputs) = &self.outputs {
+                // Outputs are accumulated over a single frame
+                encoder.clear_buffer(&outputs.buffer, 0, None);
+            }
+            if let Some((compute_pass, output_bg)) = &self.compute_pass {
+                compute_pass.update_buffers(&self.queue, params_buffer);
+                compute_pass.execute(
                     &mut encoder,
-                    &render_tex_view,
+                    [self.render_size.x, self.render_size.y],
+                    push_constants,
+                    output_bg,
+                    &self.channels_bgs[0],
+                    &self.textures_bgs[0].1,
+                );
+            } else if let Some(vertex_pass) = &self.vertex_pass {
+                vertex_pass.update_buffers(&self.queue, params_buffer);
+                vertex_pass.execute(
+                    &mut encoder,
+                    (
+                        &render_tex_view,
+                        self.msaa_tex
+                            .as_ref()
+                            .map(|it| it.create_view(&view_desc))
+                            .as_ref(),
+                    ),
+                    self.vertex.count,
                     push_constants,
                     &self.last_render_tex_bg,
+                    &self.channels_bgs[0],
+                    &self.textures_bgs[0].1,
+                );
+            } else {
+                Self::execute_passes(
+                    &self.queue,
+                    &mut encoder,
+                    &self.shader_rpasses,
+                    &self.pass_targets,
+                    &self.channels_bgs,
+                    &self.textures_bgs,
+                    (&render_tex_view, &self.last_render_tex_bg),
+                    params_buffer,
+                    push_constants,
+                );
+            }
+            if let Some((display_tex, display_bg)) = &self.display_target {
+                self.display_pass.update_buffers(
+                    &self.queue,
+                    self.display_settings,
+                    self.color_space,
+                );
+                self.display_pass.execute(
+                    &mut encoder,
+                    &display_tex.create_view(&view_desc),
+                    display_bg,
                 );
             }
         }
@@ -234,80 +638,291 @@ impl Renderer {
         }
 
         if should_render {
-            // Copy our rendered texture to the last rendered
-            encoder.copy_texture_to_texture(
-                ImageCopyTexture {
-                    texture: &self.render_tex,
-                    mip_level: 0,
-                    origin: Origin3d::ZERO,
-                    aspect: TextureAspect::All,
-                },
-                ImageCopyTexture {
-                    texture: &self.last_render_tex,
-                    mip_level: 0,
-                    origin: Origin3d::ZERO,
-                    aspect: TextureAspect::All,
-                },
-                Extent3d {
-                    width: self.render_size.x,
-                    height: self.render_size.y,
-                    depth_or_array_layers: 1,
-                },
+            // Copy our rendered textures to the last rendered
+            for target in self.pass_targets.iter() {
+                Self::copy_to_last(
+                    &mut encoder,
+                    &target.tex,
+                    &target.last_tex,
+                    self.render_size,
+                );
+            }
+            Self::copy_to_last(
+                &mut encoder,
+                &self.render_tex,
+                &self.last_render_tex,
+                self.render_size,
             );
         }
 
+        let copy_outputs = match self.outputs.as_mut() {
+            Some(outputs) if should_render && outputs.requested && outputs.mapping.is_none() => {
+                encoder.copy_buffer_to_buffer(
+                    &outputs.buffer,
+                    0,
+                    &outputs.staging,
+                    0,
+                    outputs.size,
+                );
+                outputs.requested = false;
+                true
+            }
+            _ => false,
+        };
+
         // Launch !
         self.queue.submit(Some(encoder.finish()));
         frame.present();
+
+        if copy_outputs {
+            let outputs = self.outputs.as_mut().unwrap();
+            outputs.mapping = Some(Box::pin(outputs.staging.slice(..).map_async(MapMode::Read)));
+        }
         Ok(())
     }
 
+    /// Read back the outputs of the displayed pass with the next rendered frame
+    pub fn request_outputs(&mut self) {
+        if let Some(outputs) = self.outputs.as_mut() {
+            outputs.requested = true;
+        }
+    }
+
+    /// The content of the output buffer once a requested read back is done
+    pub fn read_outputs(&mut self) -> Option<Vec<u8>> {
+        let outputs = self.outputs.as_mut()?;
+        let mapping = outputs.mapping.as_mut()?;
+        self.device.poll(Maintain::Poll);
+        // The mapping future is only polled, it is woken by polling the device
+        let result = match mapping
+            .as_mut()
+            .poll(&mut TaskContext::from_waker(Waker::noop()))
+        {
+            Poll::Ready(result) => result,
+            Poll::Pending => return None,
+        };
+        outputs.mapping = None;
+        match result {
+            Ok(()) => {
+                let bytes = outputs.staging.slice(..).get_mapped_range().to_vec();
+                outputs.staging.unmap();
+                Some(bytes)
+            }
+            Err(e) => {
+                error!("Can't read the outputs : {}", e);
+                None
+            }
+        }
+    }
+
+    /// Render a single frame offscreen at the given size, the displayed pass samples the previous
+    /// frame of the canvas. The other passes continue from the canvas, see [Self::restore_history].
     pub fn render_to_buffer(
         &self,
         render_size: Vector2<u32>,
         params_buffer: &[u8],
         push_constants: &[u8],
-        consume: impl FnOnce(BufferView) -> Result<()>,
+        consume: impl FnOnce(Vec<u8>) -> Result<()>,
     ) -> Result<()> {
-        if render_size.x % 64 != 0 {
-            error!("Render size must be a multiple of 64 because reasons");
-            return Err(anyhow::anyhow!("Invalid render size"));
+        let offscreen = self.create_offscreen(render_size, false, false)?;
+        self.restore_history(&offscreen, params_buffer, push_constants);
+        self.render_offscreen(&offscreen, params_buffer, push_constants, consume)
+    }
+
+    /// Render a single frame offscreen like [Self::render_to_buffer] and read back the float output
+    /// of the displayed pass, before it is converted for display. Without a float target, the
+    /// displayed pass is rendered to a half float texture with a pipeline of its own.
+    pub fn render_to_float_buffer(
+        &self,
+        render_size: Vector2<u32>,
+        params_buffer: &[u8],
+        push_constants: &[u8],
+        consume: impl FnOnce(Vec<f32>) -> Result<()>,
+    ) -> Result<()> {
+        let mut offscreen = self.create_offscreen(render_size, false, true)?;
+        // The displayed pass is rendered again to a float texture
+        if offscreen.output_format != self.target_texture_format() {
+            let sizes = (push_constants.len() as u32, params_buffer.len() as u64);
+            if self.kind == ShaderKind::Vertex {
+                offscreen.vertex_pass =
+                    Some(self.create_vertex_pass(FLOAT_EXPORT_FORMAT, sizes.0, sizes.1));
+            } else {
+                offscreen.rpass =
+                    Some(self.create_displayed_rpass(FLOAT_EXPORT_FORMAT, sizes.0, sizes.1));
+            }
         }
+        self.restore_history(&offscreen, params_buffer, push_constants);
+        let format = offscreen.output_format;
+        self.render_offscreen(&offscreen, params_buffer, push_constants, |bytes| {
+            consume(match format {
+                TextureFormat::Rgba16Float => bytes
+                    .chunks_exact(2)
+                    .map(|it| f16::from_ne_bytes(it.try_into().unwrap()).to_f32())
+                    .collect(),
+                _ => bytes
+                    .chunks_exact(4)
+                    .map(|it| f32::from_ne_bytes(it.try_into().unwrap()))
+                    .collect(),
+            })
+        })
+    }
 
-        let render_tex_desc = TextureDescriptor {
-            label: Some("one time render"),
-            size: Extent3d {
-                width: render_size.x,
-                height: render_size.y,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: self.format,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+    /// Render the frames of a clock offscreen one after the other, each frame samples the previous
+    /// one. The previous frames start empty. The params are keyframed from `sliders`, `inputs` sets
+    /// the other inputs of each frame and `consume` receives the pixels of each frame.
+    pub fn render_sequence(
+        &self,
+        render_size: Vector2<u32>,
+        clock: &FrameClock,
+        globals: &Globals,
+        sliders: &[Slider],
+        inputs: impl FnMut(u32, &mut Globals),
+        mut consume: impl FnMut(u32, Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let offscreen = self.create_offscreen(render_size, true, false)?;
+        clock.run(globals, sliders, inputs, |frame, globals, params| {
+            self.render_offscreen(
+                &offscreen,
+                params,
+                globals.as_std430().as_bytes(),
+                |pixels| consume(frame, pixels),
+            )
+        })
+    }
+
+    /// Create the textures of an offscreen render.
+    /// Without feedback, the displayed pass samples the previous frame of the canvas.
+    /// A float output is read back from the float target, or from a half float texture when the
+    /// target isn't float, without the display pass.
+    fn create_offscreen(
+        &self,
+        render_size: Vector2<u32>,
+        feedback: bool,
+        float: bool,
+    ) -> Result<Offscreen> {
+        let format = match self.target_texture_format() {
+            format @ (TextureFormat::Rgba16Float | TextureFormat::Rgba32Float) => format,
+            _ if float && self.kind == ShaderKind::Compute => {
+                return Err(anyhow!(
+                    "Compute shaders compile their output format, high bit depth exports need a float NUANCE_TARGET_FORMAT"
+                ))
+            }
+            _ if float => FLOAT_EXPORT_FORMAT,
+            format => format,
+        };
+        let render_tex = Self::create_render_tex(&self.device, render_size, format);
+        let last_render_tex =
+            feedback.then(|| Self::create_last_render_tex(&self.device, render_size, format));
+        let last_tex = last_render_tex.as_ref().unwrap_or(&self.last_render_tex);
+        let last_render_tex_bg =
+            Self::create_last_render_tex_bg(&self.device, last_tex, self.displayed_previous());
+        // The output is converted like on the canvas
+        let display_tex = (!float && self.needs_display_pass())
+            .then(|| Self::create_render_tex(&self.device, render_size, self.format));
+        let output_format = if display_tex.is_some() {
+            self.format
+        } else {
+            format
         };
-        let render_tex = self.device.create_texture(&render_tex_desc);
 
-        let output_buffer_size = (4 * render_size.x * render_size.y) as BufferAddress;
-        let output_buffer_desc = BufferDescriptor {
-            size: output_buffer_size,
+        // Texture copies need rows aligned to 256 bytes
+        let texel_size = output_format.describe().block_size as u32;
+        let padded_bytes_per_row = (texel_size * render_size.x)
+            .div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let output_buffer = self.device.create_buffer(&BufferDescriptor {
+            size: (padded_bytes_per_row * render_size.y) as BufferAddress,
             usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
             label: None,
             mapped_at_creation: false,
-        };
-        let output_buffer = self.device.create_buffer(&output_buffer_desc);
+        });
 
-        let shader_rpass = ShaderRenderPass::new(
-            &self.device,
-            self.shader_module.as_ref().unwrap(),
-            &self.last_render_tex_bgl,
-            push_constants.len() as u32,
-            params_buffer.len() as u64,
-            self.format,
-        );
+        // The passes before the displayed one start from empty textures
+        let pass_targets: Vec<PassTarget> = self.passes[..self.passes.len().saturating_sub(1)]
+            .iter()
+            .map(|pass| PassTarget::new(&self.device, render_size, pass.previous))
+            .collect();
+        let channels_bgs = self.create_channels_bind_groups(&pass_targets, &render_tex, last_tex);
+        let compute_output_bg = self.compute_pass.as_ref().map(|(compute_pass, _)| {
+            compute_pass.create_output_bind_group(
+                &self.device,
+                &last_tex.create_view(&TextureViewDescriptor::default()),
+                &Self::create_last_render_tex_sampler(&self.device, self.displayed_previous()),
+                &render_tex.create_view(&TextureViewDescriptor::default()),
+            )
+        });
+
+        Ok(Offscreen {
+            size: render_size,
+            msaa_tex: self.create_msaa_tex(render_size, format),
+            render_tex,
+            last_render_tex,
+            last_render_tex_bg,
+            display_tex,
+            pass_targets,
+            channels_bgs,
+            compute_output_bg,
+            rpass: None,
+            vertex_pass: None,
+            output_format,
+            output_buffer,
+            padded_bytes_per_row,
+        })
+    }
+
+    /// Give the passes not displayed of a single frame render their previous outputs. At the size of
+    /// the canvas, they are copied from the canvas. At another size, the history of the canvas can't
+    /// be used : the passes are rendered for [WARM_UP_FRAMES] frames from empty textures, with the
+    /// same globals.
+    fn restore_history(&self, offscreen: &Offscreen, params_buffer: &[u8], push_constants: &[u8]) {
+        if offscreen.pass_targets.is_empty() {
+            return;
+        }
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("offscreen history"),
+            });
+        if offscreen.size == self.render_size {
+            for (target, live) in offscreen.pass_targets.iter().zip(self.pass_targets.iter()) {
+                Self::copy_to_last(&mut encoder, &live.tex, &target.last_tex, offscreen.size);
+            }
+        } else {
+            let render_tex_view = offscreen
+                .render_tex
+                .create_view(&TextureViewDescriptor::default());
+            for _ in 0..WARM_UP_FRAMES {
+                Self::execute_passes(
+                    &self.queue,
+                    &mut encoder,
+                    &self.shader_rpasses[..offscreen.pass_targets.len()],
+                    &offscreen.pass_targets,
+                    &offscreen.channels_bgs,
+                    &self.textures_bgs,
+                    (&render_tex_view, &offscreen.last_render_tex_bg),
+                    params_buffer,
+                    push_constants,
+                );
+                for target in offscreen.pass_targets.iter() {
+                    Self::copy_to_last(&mut encoder, &target.tex, &target.last_tex, offscreen.size);
+                }
+            }
+        }
+        self.queue.submit(Some(encoder.finish()));
+    }
 
-        let render_tex_view = render_tex.create_view(&TextureViewDescriptor::default());
+    /// Render a frame to the textures of an offscreen render and read back the displayed output.
+    /// `consume` receives the rows of pixels without their padding.
+    fn render_offscreen(
+        &self,
+        offscreen: &Offscreen,
+        params_buffer: &[u8],
+        push_constants: &[u8],
+        consume: impl FnOnce(Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let render_size = offscreen.size;
+        let view_desc = TextureViewDescriptor::default();
+        let render_tex_view = offscreen.render_tex.create_view(&view_desc);
 
         // This pack a set of render passes for the gpu to execute
         let mut encoder = self
@@ -316,26 +931,80 @@ impl Renderer {
                 label: Some("image render"),
             });
 
-        shader_rpass.update_buffers(&self.queue, params_buffer);
-        shader_rpass.execute(
-            &mut encoder,
-            &render_tex_view,
-            push_constants,
-            &self.last_render_tex_bg,
-        );
+        if let (Some((compute_pass, _)), Some(output_bg)) =
+            (&self.compute_pass, &offscreen.compute_output_bg)
+        {
+            compute_pass.update_buffers(&self.queue, params_buffer);
+            compute_pass.execute(
+                &mut encoder,
+                [render_size.x, render_size.y],
+                push_constants,
+                output_bg,
+                &offscreen.channels_bgs[0],
+                &self.textures_bgs[0].1,
+            );
+        } else if let Some(vertex_pass) =
+            offscreen.vertex_pass.as_ref().or(self.vertex_pass.as_ref())
+        {
+            vertex_pass.update_buffers(&self.queue, params_buffer);
+            vertex_pass.execute(
+                &mut encoder,
+                (
+                    &render_tex_view,
+                    offscreen
+                        .msaa_tex
+                        .as_ref()
+                        .map(|it| it.create_view(&view_desc))
+                        .as_ref(),
+                ),
+                self.vertex.count,
+                push_constants,
+                &offscreen.last_render_tex_bg,
+                &offscreen.channels_bgs[0],
+                &self.textures_bgs[0].1,
+            );
+        } else {
+            let hidden = &self.shader_rpasses[..offscreen.pass_targets.len()];
+            let displayed = offscreen.rpass.as_ref().or(self.shader_rpasses.last());
+            Self::execute_passes(
+                &self.queue,
+                &mut encoder,
+                hidden.iter().chain(displayed),
+                &offscreen.pass_targets,
+                &offscreen.channels_bgs,
+                &self.textures_bgs,
+                (&render_tex_view, &offscreen.last_render_tex_bg),
+                params_buffer,
+                push_constants,
+            );
+        }
+        if let Some(display_tex) = &offscreen.display_tex {
+            self.display_pass
+                .update_buffers(&self.queue, self.display_settings, self.color_space);
+            self.display_pass.execute(
+                &mut encoder,
+                &display_tex.create_view(&view_desc),
+                &self
+                    .display_pass
+                    .create_bind_group(&self.device, &render_tex_view),
+            );
+        }
 
         encoder.copy_texture_to_buffer(
             ImageCopyTexture {
-                texture: &render_tex,
+                texture: offscreen
+                    .display_tex
+                    .as_ref()
+                    .unwrap_or(&offscreen.render_tex),
                 mip_level: 0,
                 origin: Origin3d::ZERO,
                 aspect: TextureAspect::All,
             },
             ImageCopyBuffer {
-                buffer: &output_buffer,
+                buffer: &offscreen.output_buffer,
                 layout: ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: NonZeroU32::new(4 * render_size.x),
+                    bytes_per_row: NonZeroU32::new(offscreen.padded_bytes_per_row),
                     rows_per_image: NonZeroU32::new(render_size.y),
                 },
             },
@@ -345,29 +1014,283 @@ impl Renderer {
                 depth_or_array_layers: 1,
             },
         );
+
+        // The next frame samples this one
+        if let Some(last_render_tex) = &offscreen.last_render_tex {
+            for target in offscreen.pass_targets.iter() {
+                Self::copy_to_last(&mut encoder, &target.tex, &target.last_tex, render_size);
+            }
+            Self::copy_to_last(
+                &mut encoder,
+                &offscreen.render_tex,
+                last_render_tex,
+                render_size,
+            );
+        }
         self.queue.submit(Some(encoder.finish()));
 
-        let slice = output_buffer.slice(..);
+        let slice = offscreen.output_buffer.slice(..);
         let mapping = slice.map_async(MapMode::Read);
         self.device.poll(Maintain::Wait);
         futures_executor::block_on(mapping)?;
-        let view = slice.get_mapped_range();
+        let row_size =
+            (offscreen.output_format.describe().block_size as u32 * render_size.x) as usize;
+        let mut pixels: Vec<u8> = slice
+            .get_mapped_range()
+            .chunks_exact(offscreen.padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..row_size])
+            .copied()
+            .collect();
+        offscreen.output_buffer.unmap();
+        // The surface format may store blue first, exports are RGBA
+        if matches!(
+            offscreen.output_format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        consume(pixels)
+    }
+
+    /// Render a sound shader block by block.
+    /// Returns the interleaved left and right samples.
+    pub fn render_sound(
+        &self,
+        shader_source: ShaderSource,
+        settings: SoundSettings,
+    ) -> Result<Vec<f32>> {
+        let module = self.device.create_shader_module(&ShaderModuleDescriptor {
+            label: Some("nuance sound shader"),
+            source: shader_source,
+        });
+        let sound_pass = SoundPass::new(&self.device, &module);
+
+        let [width, height] = SOUND_BLOCK_SIZE;
+        let block_size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let block_tex = self.device.create_texture(&TextureDescriptor {
+            label: Some("sound block"),
+            size: block_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: renderer::SOUND_TEXTURE_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        });
+        let block_tex_view = block_tex.create_view(&TextureViewDescriptor::default());
+        // 2 floats per texel
+        let bytes_per_row = 8 * width;
+        let output_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("sound block buffer"),
+            size: (bytes_per_row * height) as BufferAddress,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let sample_count = settings.sample_count();
+        let mut samples = Vec::with_capacity(2 * sample_count as usize);
+        for offset in (0..sample_count).step_by((width * height) as usize) {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("sound render"),
+                });
+            sound_pass.execute(
+                &mut encoder,
+                &block_tex_view,
+                &settings.block_push_constants(offset),
+            );
+            encoder.copy_texture_to_buffer(
+                ImageCopyTexture {
+                    texture: &block_tex,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyBuffer {
+                    buffer: &output_buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(bytes_per_row),
+                        rows_per_image: NonZeroU32::new(height),
+                    },
+                },
+                block_size,
+            );
+            self.queue.submit(Some(encoder.finish()));
 
-        consume(view)
+            let slice = output_buffer.slice(..);
+            let mapping = slice.map_async(MapMode::Read);
+            self.device.poll(Maintain::Wait);
+            futures_executor::block_on(mapping)?;
+            {
+                let view = slice.get_mapped_range();
+                // The last block is only partially used
+                let count = 2 * (sample_count - offset).min(width * height) as usize;
+                samples.extend(
+                    view.chunks_exact(4)
+                        .take(count)
+                        .map(|it| f32::from_ne_bytes(it.try_into().unwrap())),
+                );
+            }
+            output_buffer.unmap();
+        }
+        Ok(samples)
     }
 
     pub fn resize_inner_canvas(&mut self, size: Vector2<u32>) {
         self.render_size = size;
-        self.render_tex = Self::create_render_tex(&self.device, size, self.format);
-        let temp = Self::create_last_render_tex(&self.device, size, self.format);
-        self.last_render_tex = temp.0;
-        self.last_render_tex_bgl = temp.1;
-        self.last_render_tex_bg = temp.2;
+        self.create_render_targets();
+    }
+
+    /// Change the format of the displayed pass output, this recreates its textures and pipeline
+    pub fn set_target_format(
+        &mut self,
+        target_format: TargetFormat,
+        push_constant_size: u32,
+        params_buffer_size: u64,
+    ) {
+        if target_format != self.target_format {
+            self.target_format = target_format;
+            self.create_render_targets();
+            self.create_rpasses(push_constant_size, params_buffer_size);
+            self.warn_unfilterable_channels();
+        }
+    }
+
+    /// Change how the output colors are encoded, this may change the format of the displayed pass
+    pub fn set_color_space(
+        &mut self,
+        color_space: ColorSpace,
+        push_constant_size: u32,
+        params_buffer_size: u64,
+    ) {
+        if color_space != self.color_space {
+            self.color_space = color_space;
+            self.create_render_targets();
+            self.create_rpasses(push_constant_size, params_buffer_size);
+        }
+    }
+
+    /// Change how the primitives of vertex shaders are drawn, the pipeline is only recreated when
+    /// more than the vertex count changes
+    pub fn set_vertex_settings(
+        &mut self,
+        vertex: VertexSettings,
+        push_constant_size: u32,
+        params_buffer_size: u64,
+    ) {
+        let rebuild = VertexSettings {
+            count: self.vertex.count,
+            ..vertex
+        } != self.vertex;
+        self.vertex = vertex;
+        if rebuild && self.kind == ShaderKind::Vertex {
+            self.create_render_targets();
+            self.create_rpasses(push_constant_size, params_buffer_size);
+        }
+    }
 
+    pub fn set_display_settings(&mut self, settings: DisplaySettings) {
+        self.display_settings = settings;
+    }
+
+    /// Format of the texture the displayed pass renders to
+    fn target_texture_format(&self) -> TextureFormat {
+        if self.kind == ShaderKind::Compute {
+            renderer::storage_texture_format(self.target_format)
+        } else {
+            renderer::target_texture_format(self.target_format, self.color_space, self.format)
+        }
+    }
+
+    /// The displayed pass output is converted to the surface format unless it is already in it
+    fn needs_display_pass(&self) -> bool {
+        self.target_texture_format() != self.format
+    }
+
+    /// How the displayed pass samples its previous frame, Rgba32Float textures can't be filtered
+    fn displayed_previous(&self) -> PreviousSampler {
+        let mut previous = self.passes.last().map(|it| it.previous).unwrap_or_default();
+        if self.target_format == TargetFormat::Rgba32Float {
+            previous.filter = Filter::Nearest;
+        }
+        previous
+    }
+
+    /// Channels bound to the output of the displayed pass when it renders to Rgba32Float, as
+    /// (pass, channel). Channel samplers filter, which Rgba32Float textures don't support, so
+    /// these channels are bound to an empty texture.
+    pub fn unfilterable_channels(&self) -> Vec<(usize, usize)> {
+        if self.target_format != TargetFormat::Rgba32Float {
+            return Vec::new();
+        }
+        let displayed = self.passes.len().saturating_sub(1);
+        self.passes
+            .iter()
+            .enumerate()
+            .flat_map(|(pass, it)| {
+                it.channels
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, channel)| {
+                        matches!(channel, Channel::Pass { index, .. } if *index == displayed)
+                    })
+                    .map(move |(channel, _)| (pass, channel))
+            })
+            .collect()
+    }
+
+    fn warn_unfilterable_channels(&self) {
+        for (pass, channel) in self.unfilterable_channels() {
+            warn!(
+                "Channel {} of pass {} samples the displayed pass, which renders to Rgba32Float and can't be filtered. It is bound to an empty texture.",
+                channel, self.passes[pass].name
+            );
+        }
+    }
+
+    /// Recreate the textures rendered to at the canvas size and show the displayed one
+    fn create_render_targets(&mut self) {
+        let format = self.target_texture_format();
+        self.render_tex = Self::create_render_tex(&self.device, self.render_size, format);
+        self.last_render_tex = Self::create_last_render_tex(&self.device, self.render_size, format);
+        self.msaa_tex = self.create_msaa_tex(self.render_size, self.target_texture_format());
+        self.display_target = self.needs_display_pass().then(|| {
+            let display_tex = Self::create_render_tex(&self.device, self.render_size, self.format);
+            let display_bg = self.display_pass.create_bind_group(
+                &self.device,
+                &self
+                    .render_tex
+                    .create_view(&TextureViewDescriptor::default()),
+            );
+            (display_tex, display_bg)
+        });
+        self.create_pass_targets();
+        self.channels_bgs = self.create_channels_bind_groups(
+            &self.pass_targets,
+            &self.render_tex,
+            &self.last_render_tex,
+        );
+        if let Some((compute_pass, _)) = &self.compute_pass {
+            let output_bg = self.create_compute_output_bg(compute_pass, &self.render_tex);
+            self.compute_pass.as_mut().unwrap().1 = output_bg;
+        }
+
+        let canvas_tex = self
+            .display_target
+            .as_ref()
+            .map(|(tex, _)| tex)
+            .unwrap_or(&self.render_tex);
         self.egui_rpass
             .update_egui_texture_from_wgpu_texture(
                 &self.device,
-                &self.render_tex.create_view(&TextureViewDescriptor {
+                &canvas_tex.create_view(&TextureViewDescriptor {
                     label: None,
                     format: Some(self.format),
                     dimension: Some(TextureViewDimension::D2),
@@ -380,7 +1303,7 @@ impl Renderer {
                 FilterMode::Linear,
                 TextureId::User(0),
             )
-            .expect("Can't update canvas texture following resize");
+            .expect("Can't update the canvas texture");
     }
 
     pub fn resize(&mut self, size: Vector2<u32>) {
@@ -408,18 +1331,49 @@ impl Renderer {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: TextureUsages::RENDER_ATTACHMENT
-                | TextureUsages::TEXTURE_BINDING
-                | TextureUsages::COPY_SRC,
+            usage: Self::render_tex_usages(format),
         };
         device.create_texture(&render_tex_desc)
     }
 
+    /// Multisampled texture the vertex shaders draw to when multisampling is enabled
+    fn create_msaa_tex(&self, size: Vector2<u32>, format: TextureFormat) -> Option<Texture> {
+        let sample_count = renderer::vertex_sample_count(self.vertex, format);
+        (self.kind == ShaderKind::Vertex && sample_count > 1).then(|| {
+            self.device.create_texture(&TextureDescriptor {
+                label: Some("shader msaa tex"),
+                size: Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+            })
+        })
+    }
+
+    /// Usages of the textures rendered to, compute shaders write to storage textures
+    fn render_tex_usages(format: TextureFormat) -> TextureUsages {
+        let usages = TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC;
+        match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba16Float | TextureFormat::Rgba32Float => {
+                usages | TextureUsages::STORAGE_BINDING
+            }
+            _ => usages,
+        }
+    }
+
     fn create_last_render_tex(
         device: &Device,
         size: Vector2<u32>,
         format: TextureFormat,
-    ) -> (Texture, BindGroupLayout, BindGroup) {
+    ) -> Texture {
         let last_render_tex_desc = TextureDescriptor {
             label: Some("shader last render tex"),
             size: Extent3d {
@@ -433,24 +1387,23 @@ impl Renderer {
             format,
             usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
         };
-        let last_render_tex = device.create_texture(&last_render_tex_desc);
-
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("last render tex sampler"),
-            address_mode_u: AddressMode::Repeat,
-            address_mode_v: AddressMode::Repeat,
-            address_mode_w: AddressMode::Repeat,
-            ..Default::default()
-        });
+        device.create_texture(&last_render_tex_desc)
+    }
 
-        let last_render_tex_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: None,
+    /// Layout of the previous frame bind group (set 0), the texture is filterable only when the
+    /// sampler filters
+    fn create_last_render_tex_bgl(device: &Device, previous: PreviousSampler) -> BindGroupLayout {
+        let filtering = previous.filter == Filter::Linear;
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("last tex bind group layout"),
             entries: &[
                 BindGroupLayoutEntry {
                     binding: 0,
                     visibility: ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: false },
+                        sample_type: TextureSampleType::Float {
+                            filterable: filtering,
+                        },
                         view_dimension: TextureViewDimension::D2,
                         multisampled: false,
                     },
@@ -459,40 +1412,421 @@ impl Renderer {
                 BindGroupLayoutEntry {
                     binding: 1,
                     visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Sampler {
-                        0: SamplerBindingType::NonFiltering,
-                    },
+                    ty: BindingType::Sampler(if filtering {
+                        SamplerBindingType::Filtering
+                    } else {
+                        SamplerBindingType::NonFiltering
+                    }),
                     count: None,
                 },
             ],
-        });
+        })
+    }
+
+    /// Bind group of a compute pass writing to the given texture
+    fn create_compute_output_bg(&self, compute_pass: &ComputePass, output: &Texture) -> BindGroup {
+        let view_desc = TextureViewDescriptor::default();
+        compute_pass.create_output_bind_group(
+            &self.device,
+            &self.last_render_tex.create_view(&view_desc),
+            &Self::create_last_render_tex_sampler(&self.device, self.displayed_previous()),
+            &output.create_view(&view_desc),
+        )
+    }
+
+    fn create_last_render_tex_bg(
+        device: &Device,
+        last_render_tex: &Texture,
+        previous: PreviousSampler,
+    ) -> BindGroup {
+        let sampler = Self::create_last_render_tex_sampler(device, previous);
 
-        let last_render_tex_bg = device.create_bind_group(&BindGroupDescriptor {
+        device.create_bind_group(&BindGroupDescriptor {
             label: Some("last tex bind group"),
-            layout: &last_render_tex_bgl,
+            layout: &Self::create_last_render_tex_bgl(device, previous),
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(&last_render_tex.create_view(
-                        &TextureViewDescriptor {
-                            label: None,
-                            format: Some(format),
-                            dimension: Some(TextureViewDimension::D2),
-                            aspect: TextureAspect::All,
-                            base_mip_level: 0,
-                            mip_level_count: None,
-                            base_array_layer: 0,
-                            array_layer_count: None,
-                        },
-                    )),
+                    resource: BindingResource::TextureView(
+                        &last_render_tex.create_view(&TextureViewDescriptor::default()),
+                    ),
                 },
                 BindGroupEntry {
                     binding: 1,
                     resource: BindingResource::Sampler(&sampler),
                 },
             ],
-        });
+        })
+    }
+
+    fn create_last_render_tex_sampler(device: &Device, previous: PreviousSampler) -> Sampler {
+        let address_mode = match previous.wrap {
+            Wrap::Clamp => AddressMode::ClampToEdge,
+            Wrap::Repeat => AddressMode::Repeat,
+            Wrap::Mirror => AddressMode::MirrorRepeat,
+        };
+        let filter = match previous.filter {
+            Filter::Nearest => FilterMode::Nearest,
+            Filter::Linear => FilterMode::Linear,
+        };
+        device.create_sampler(&SamplerDescriptor {
+            label: Some("last render tex sampler"),
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            ..Default::default()
+        })
+    }
+
+    /// Create the output textures of the passes before the displayed one
+    fn create_pass_targets(&mut self) {
+        self.pass_targets = self.passes[..self.passes.len().saturating_sub(1)]
+            .iter()
+            .map(|pass| PassTarget::new(&self.device, self.render_size, pass.previous))
+            .collect();
+        self.last_render_tex_bg = self.create_displayed_last_render_tex_bg();
+    }
+
+    fn create_displayed_last_render_tex_bg(&self) -> BindGroup {
+        Self::create_last_render_tex_bg(
+            &self.device,
+            &self.last_render_tex,
+            self.displayed_previous(),
+        )
+    }
+
+    /// Recreate the previous frame bind group of each pass
+    fn create_last_render_tex_bgs(&mut self) {
+        for (target, pass) in self.pass_targets.iter_mut().zip(self.passes.iter()) {
+            target.last_tex_bg =
+                Self::create_last_render_tex_bg(&self.device, &target.last_tex, pass.previous);
+        }
+        self.last_render_tex_bg = self.create_displayed_last_render_tex_bg();
+    }
 
-        (last_render_tex, last_render_tex_bgl, last_render_tex_bg)
+    /// Create the texture bound to empty channels
+    fn create_empty_channel_tex(device: &Device) -> Texture {
+        // Textures are zero initialized
+        device.create_texture(&TextureDescriptor {
+            label: Some("empty channel tex"),
+            size: Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING,
+        })
+    }
+
+    /// Create the samplers used by the channels of the passes
+    fn create_channel_samplers(&mut self, passes: &[Pass]) {
+        for sampler in passes.iter().flat_map(|it| it.samplers.iter()) {
+            if !self.channel_samplers.contains_key(sampler) {
+                let address_mode = match sampler.wrap {
+                    Wrap::Clamp => AddressMode::ClampToEdge,
+                    Wrap::Repeat => AddressMode::Repeat,
+                    Wrap::Mirror => AddressMode::MirrorRepeat,
+                };
+                let filter = match sampler.filter {
+                    Filter::Nearest => FilterMode::Nearest,
+                    Filter::Linear => FilterMode::Linear,
+                };
+                let created = self.device.create_sampler(&SamplerDescriptor {
+                    label: Some("channel sampler"),
+                    address_mode_u: address_mode,
+                    address_mode_v: address_mode,
+                    address_mode_w: address_mode,
+                    mag_filter: filter,
+                    min_filter: filter,
+                    mipmap_filter: filter,
+                    // Only the full size image is sampled without mipmaps
+                    lod_max_clamp: if sampler.mipmaps { f32::MAX } else { 0.0 },
+                    ..Default::default()
+                });
+                self.channel_samplers.insert(*sampler, created);
+            }
+        }
+    }
+
+    /// Load the images used by the passes which are not loaded yet
+    fn load_textures(
+        device: &Device,
+        queue: &Queue,
+        passes: &[Pass],
+        loaded: &mut HashMap<TextureFile, Texture>,
+    ) -> Result<()> {
+        for pass in passes.iter() {
+            let channels = pass.channels.iter().filter_map(|channel| match channel {
+                Channel::Texture(path) => Some(TextureFile::Image(path.clone())),
+                _ => None,
+            });
+            for file in channels.chain(pass.textures.iter().cloned()) {
+                if let Entry::Vacant(entry) = loaded.entry(file) {
+                    let texture = textures::load(device, queue, entry.key())?;
+                    entry.insert(texture);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode the audio files bound to the channels which are not loaded yet
+    fn load_audio(
+        device: &Device,
+        passes: &[Pass],
+        loaded: &mut HashMap<PathBuf, (Audio, Texture)>,
+    ) -> Result<()> {
+        for pass in passes.iter() {
+            for channel in pass.channels.iter() {
+                if let Channel::Audio(path) = channel {
+                    if let Entry::Vacant(entry) = loaded.entry(path.clone()) {
+                        let audio = Audio::load(path)?;
+                        let texture = device.create_texture(&TextureDescriptor {
+                            label: Some("audio channel tex"),
+                            size: Extent3d {
+                                width: AUDIO_TEXTURE_WIDTH,
+                                height: 2,
+                                depth_or_array_layers: 1,
+                            },
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: TextureDimension::D2,
+                            format: TextureFormat::R8Unorm,
+                            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                        });
+                        entry.insert((audio, texture));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Analyze the audio channels at the given time and upload their textures.
+    /// Returns the levels of the first audio channel, in pass order.
+    pub fn update_audio(&self, time: f32) -> Option<[f32; 3]> {
+        puffin::profile_function!();
+        let mut levels = None;
+        let mut analyzed = Vec::new();
+        for channel in self.passes.iter().flat_map(|it| it.channels.iter()) {
+            let path = match channel {
+                Channel::Audio(path) if !analyzed.contains(&path) => path,
+                _ => continue,
+            };
+            if let Some((audio, texture)) = self.audio.get(path) {
+                let frame = audio.analyze(time);
+                self.queue.write_texture(
+                    texture.as_image_copy(),
+                    &frame.texture,
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(AUDIO_TEXTURE_WIDTH),
+                        rows_per_image: None,
+                    },
+                    Extent3d {
+                        width: AUDIO_TEXTURE_WIDTH,
+                        height: 2,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                levels.get_or_insert(frame.levels);
+            }
+            analyzed.push(path);
+        }
+        levels
+    }
+
+    /// Create the channels bind group of each pass.
+    /// `pass_targets` are the outputs of the passes before the displayed one.
+    fn create_channels_bind_groups(
+        &self,
+        pass_targets: &[PassTarget],
+        render_tex: &Texture,
+        last_render_tex: &Texture,
+    ) -> Vec<BindGroup> {
+        let view_desc = TextureViewDescriptor::default();
+        let empty_view = self.empty_channel_tex.create_view(&view_desc);
+        // Current and previous output of each pass
+        let textures: HashMap<&TextureFile, TextureView> = self
+            .textures
+            .iter()
+            .map(|(path, tex)| (path, tex.create_view(&view_desc)))
+            .collect();
+        let audio: HashMap<&PathBuf, TextureView> = self
+            .audio
+            .iter()
+            .map(|(path, (_, tex))| (path, tex.create_view(&view_desc)))
+            .collect();
+        let outputs: Vec<(TextureView, TextureView)> = pass_targets
+            .iter()
+            .map(|it| (&it.tex, &it.last_tex))
+            .chain(Some((render_tex, last_render_tex)))
+            .map(|(tex, last_tex)| {
+                (
+                    tex.create_view(&view_desc),
+                    last_tex.create_view(&view_desc),
+                )
+            })
+            .collect();
+
+        let unfilterable = self.unfilterable_channels();
+
+        self.passes
+            .iter()
+            .enumerate()
+            .map(|(pass_index, pass)| {
+                let entries: Vec<BindGroupEntry> = pass
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .zip(pass.samplers.iter())
+                    .flat_map(|((i, channel), sampler)| {
+                        let view = match channel {
+                            _ if unfilterable.contains(&(pass_index, i)) => &empty_view,
+                            Channel::Pass { index, previous } if *index < outputs.len() => {
+                                if *previous {
+                                    &outputs[*index].1
+                                } else {
+                                    &outputs[*index].0
+                                }
+                            }
+                            Channel::Texture(path) => textures
+                                .get(&TextureFile::Image(path.clone()))
+                                .unwrap_or(&empty_view),
+                            Channel::Audio(path) => audio.get(path).unwrap_or(&empty_view),
+                            _ => &empty_view,
+                        };
+                        [
+                            BindGroupEntry {
+                                binding: 2 * i as u32,
+                                resource: BindingResource::TextureView(view),
+                            },
+                            BindGroupEntry {
+                                binding: 2 * i as u32 + 1,
+                                resource: BindingResource::Sampler(&self.channel_samplers[sampler]),
+                            },
+                        ]
+                    })
+                    .collect();
+
+                self.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("channels bind group"),
+                    layout: &self.channels_bgl,
+                    entries: &entries,
+                })
+            })
+            .collect()
+    }
+
+    /// Create the bind group of the textures declared by each pass, along with its layout
+    fn create_textures_bind_groups(&self) -> Vec<(BindGroupLayout, BindGroup)> {
+        self.passes
+            .iter()
+            .map(|pass| {
+                let layout =
+                    renderer::create_textures_bind_group_layout(&self.device, &pass.textures);
+                let views: Vec<TextureView> = pass
+                    .textures
+                    .iter()
+                    .map(|file| {
+                        self.textures[file].create_view(&TextureViewDescriptor {
+                            dimension: Some(renderer::texture_view_dimension(file)),
+                            ..Default::default()
+                        })
+                    })
+                    .collect();
+                let entries: Vec<BindGroupEntry> = views
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, view)| {
+                        [
+                            BindGroupEntry {
+                                binding: 2 * i as u32,
+                                resource: BindingResource::TextureView(view),
+                            },
+                            BindGroupEntry {
+                                binding: 2 * i as u32 + 1,
+                                resource: BindingResource::Sampler(&self.texture_sampler),
+                            },
+                        ]
+                    })
+                    .collect();
+                let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("textures bind group"),
+                    layout: &layout,
+                    entries: &entries,
+                });
+                (layout, bind_group)
+            })
+            .collect()
+    }
+
+    /// Render all passes in order, the last one to the given output
+    #[allow(clippy::too_many_arguments)]
+    fn execute_passes<'a>(
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        rpasses: impl IntoIterator<Item = &'a ShaderRenderPass>,
+        pass_targets: &[PassTarget],
+        channels_bgs: &[BindGroup],
+        textures_bgs: &[(BindGroupLayout, BindGroup)],
+        output: (&TextureView, &BindGroup),
+        params_buffer: &[u8],
+        push_constants: &[u8],
+    ) {
+        for (i, rpass) in rpasses.into_iter().enumerate() {
+            rpass.update_buffers(queue, params_buffer);
+            match pass_targets.get(i) {
+                Some(target) => rpass.execute(
+                    encoder,
+                    &target.tex.create_view(&TextureViewDescriptor::default()),
+                    push_constants,
+                    &target.last_tex_bg,
+                    &channels_bgs[i],
+                    &textures_bgs[i].1,
+                ),
+                None => rpass.execute(
+                    encoder,
+                    output.0,
+                    push_constants,
+                    output.1,
+                    &channels_bgs[i],
+                    &textures_bgs[i].1,
+                ),
+            }
+        }
+    }
+
+    fn copy_to_last(
+        encoder: &mut CommandEncoder,
+        tex: &Texture,
+        last_tex: &Texture,
+        size: Vector2<u32>,
+    ) {
+        encoder.copy_texture_to_texture(
+            ImageCopyTexture {
+                texture: tex,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyTexture {
+                texture: last_tex,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
     }
 }
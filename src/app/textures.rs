@@ -0,0 +1,301 @@
+//! Loading of the image files sampled by shaders
+
+use std::f32::consts::PI;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use half::f16;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, RgbaImage};
+use wgpu::*;
+
+use nuance::{TextureFile, Voxel};
+
+/// Names of the face images of a cube, in layer order
+const CUBE_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// Load the files of a texture to a new texture
+pub fn load(device: &Device, queue: &Queue, file: &TextureFile) -> Result<Texture> {
+    match file {
+        TextureFile::Image(path) => load_image(device, queue, path),
+        TextureFile::Cube(path) if path.is_dir() => load_cube_faces(device, queue, path),
+        TextureFile::Cube(path) => load_equirectangular(device, queue, path),
+        TextureFile::Volume { path, size, voxel } => {
+            load_volume(device, queue, path, *size, *voxel)
+        }
+        TextureFile::Slices(path) => load_slices(device, queue, path),
+    }
+    .with_context(|| format!("Can't load texture {}", file.path().display()))
+}
+
+fn open(path: &Path) -> Result<RgbaImage> {
+    Ok(image::open(path)
+        .with_context(|| format!("Can't open image {}", path.display()))?
+        .to_rgba8())
+}
+
+/// Write a rgba image to a layer of a texture
+fn write_layer(queue: &Queue, texture: &Texture, mip_level: u32, layer: u32, image: &RgbaImage) {
+    write(
+        queue,
+        texture,
+        mip_level,
+        layer,
+        image,
+        4,
+        [image.width(), image.height(), 1],
+    );
+}
+
+/// Write raw texels to a texture starting at the given layer
+fn write(
+    queue: &Queue,
+    texture: &Texture,
+    mip_level: u32,
+    layer: u32,
+    data: &[u8],
+    texel_size: u32,
+    size: [u32; 3],
+) {
+    queue.write_texture(
+        ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+            aspect: TextureAspect::All,
+        },
+        data,
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(texel_size * size[0]),
+            rows_per_image: NonZeroU32::new(size[1]),
+        },
+        Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: size[2],
+        },
+    );
+}
+
+fn create_texture(
+    device: &Device,
+    size: [u32; 3],
+    mip_level_count: u32,
+    dimension: TextureDimension,
+    format: TextureFormat,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("image texture"),
+        size: Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: size[2],
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+    })
+}
+
+/// Load an image file along with its mipmaps
+fn load_image(device: &Device, queue: &Queue, path: &Path) -> Result<Texture> {
+    let mut image = open(path)?;
+    let (width, height) = image.dimensions();
+    let mip_level_count = 32 - width.max(height).leading_zeros();
+    let texture = create_texture(
+        device,
+        [width, height, 1],
+        mip_level_count,
+        TextureDimension::D2,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    for mip_level in 0..mip_level_count {
+        if mip_level > 0 {
+            // Each level is downscaled from the previous one
+            image = imageops::resize(
+                &image,
+                (width >> mip_level).max(1),
+                (height >> mip_level).max(1),
+                FilterType::Triangle,
+            );
+        }
+        write_layer(queue, &texture, mip_level, 0, &image);
+    }
+    Ok(texture)
+}
+
+/// Load the 6 faces of a cube from the images in a directory
+fn load_cube_faces(device: &Device, queue: &Queue, dir: &Path) -> Result<Texture> {
+    let files = image_files(dir)?;
+    let faces = CUBE_FACES
+        .iter()
+        .map(|face| {
+            let file = files
+                .iter()
+                .find(|it| it.file_stem().is_some_and(|stem| stem == *face))
+                .ok_or_else(|| anyhow!("No image for the {} face", face))?;
+            open(file)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let size = faces[0].width();
+    if faces
+        .iter()
+        .any(|it| it.width() != size || it.height() != size)
+    {
+        return Err(anyhow!(
+            "The faces of a cube must be square and of the same size"
+        ));
+    }
+    Ok(create_cube(device, queue, size, &faces))
+}
+
+/// Project an equirectangular image on the 6 faces of a cube
+fn load_equirectangular(device: &Device, queue: &Queue, path: &Path) -> Result<Texture> {
+    let image = open(path)?;
+    let size = (image.width() / 4).max(1);
+    let faces: Vec<RgbaImage> = (0..CUBE_FACES.len())
+        .map(|face| {
+            RgbaImage::from_fn(size, size, |x, y| {
+                // Direction of the texel in the cube, from -1 to 1 on the face
+                let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                let [dx, dy, dz] = match face {
+                    0 => [1.0, -t, -s],
+                    1 => [-1.0, -t, s],
+                    2 => [s, 1.0, t],
+                    3 => [s, -1.0, -t],
+                    4 => [s, -t, 1.0],
+                    _ => [-s, -t, -1.0],
+                };
+                let length = (dx * dx + dy * dy + dz * dz).sqrt();
+                // The center of the image is towards -z, the top towards +y
+                let u = 0.5 + dx.atan2(-dz) / (2.0 * PI);
+                let v = (dy / length).acos() / PI;
+                *image.get_pixel(
+                    ((u * image.width() as f32) as u32).min(image.width() - 1),
+                    ((v * image.height() as f32) as u32).min(image.height() - 1),
+                )
+            })
+        })
+        .collect();
+    Ok(create_cube(device, queue, size, &faces))
+}
+
+fn create_cube(device: &Device, queue: &Queue, size: u32, faces: &[RgbaImage]) -> Texture {
+    let texture = create_texture(
+        device,
+        [size, size, CUBE_FACES.len() as u32],
+        1,
+        TextureDimension::D2,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    for (layer, face) in faces.iter().enumerate() {
+        write_layer(queue, &texture, 0, layer as u32, face);
+    }
+    texture
+}
+
+/// Load a raw volume of voxels, 16 bits and float voxels are stored as half floats
+fn load_volume(
+    device: &Device,
+    queue: &Queue,
+    path: &Path,
+    size: [u32; 3],
+    voxel: Voxel,
+) -> Result<Texture> {
+    let data = fs::read(path)?;
+    let voxel_size = match voxel {
+        Voxel::U8 => 1,
+        Voxel::U16 => 2,
+        Voxel::F32 => 4,
+    };
+    let expected = size.iter().product::<u32>() as usize * voxel_size;
+    if data.len() != expected {
+        return Err(anyhow!(
+            "The volume is {} bytes, expected {} bytes for {}x{}x{} voxels",
+            data.len(),
+            expected,
+            size[0],
+            size[1],
+            size[2]
+        ));
+    }
+
+    let (format, data) = match voxel {
+        Voxel::U8 => (TextureFormat::R8Unorm, data),
+        Voxel::U16 => (
+            TextureFormat::R16Float,
+            data.chunks_exact(2)
+                .flat_map(|it| {
+                    f16::from_f32(u16::from_le_bytes([it[0], it[1]]) as f32 / u16::MAX as f32)
+                        .to_bits()
+                        .to_le_bytes()
+                })
+                .collect(),
+        ),
+        Voxel::F32 => (
+            TextureFormat::R16Float,
+            data.chunks_exact(4)
+                .flat_map(|it| {
+                    f16::from_f32(f32::from_le_bytes([it[0], it[1], it[2], it[3]]))
+                        .to_bits()
+                        .to_le_bytes()
+                })
+                .collect(),
+        ),
+    };
+    let texel_size = if voxel == Voxel::U8 { 1 } else { 2 };
+    let texture = create_texture(device, size, 1, TextureDimension::D3, format);
+    write(queue, &texture, 0, 0, &data, texel_size, size);
+    Ok(texture)
+}
+
+/// Load a volume from the images of a directory, sorted by name
+fn load_slices(device: &Device, queue: &Queue, dir: &Path) -> Result<Texture> {
+    let slices = image_files(dir)?
+        .iter()
+        .map(|it| open(it))
+        .collect::<Result<Vec<_>>>()?;
+    let (width, height) = slices
+        .first()
+        .ok_or_else(|| anyhow!("No image in the directory"))?
+        .dimensions();
+    if slices.iter().any(|it| it.dimensions() != (width, height)) {
+        return Err(anyhow!("The slices of a volume must be of the same size"));
+    }
+
+    let texture = create_texture(
+        device,
+        [width, height, slices.len() as u32],
+        1,
+        TextureDimension::D3,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    for (layer, slice) in slices.iter().enumerate() {
+        write_layer(queue, &texture, 0, layer as u32, slice);
+    }
+    Ok(texture)
+}
+
+/// Images in a directory, sorted by name
+fn image_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if ImageFormat::from_path(&path).is_ok() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,192 @@
+//! Uncompressed YUV4MPEG2 video, readable by most video tools
+
+use std::io::Write;
+
+use anyhow::Result;
+use image::{ImageBuffer, Rgba};
+
+/// Resolution of the chroma planes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chroma {
+    /// Full resolution
+    C444,
+    /// Half horizontal resolution
+    C422,
+    /// Half horizontal and vertical resolution, sited at the center of each 2x2 block
+    C420,
+}
+
+impl Chroma {
+    fn tag(&self) -> &'static str {
+        match self {
+            Chroma::C444 => "444",
+            Chroma::C422 => "422",
+            Chroma::C420 => "420jpeg",
+        }
+    }
+
+    /// Number of pixels of a chroma sample, horizontally and vertically
+    fn block(&self) -> (u32, u32) {
+        match self {
+            Chroma::C444 => (1, 1),
+            Chroma::C422 => (2, 1),
+            Chroma::C420 => (2, 2),
+        }
+    }
+}
+
+/// Coefficients converting RGB to luma
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matrix {
+    /// Standard definition
+    Bt601,
+    /// High definition
+    Bt709,
+}
+
+impl Matrix {
+    /// Red and blue luma coefficients
+    fn coefficients(&self) -> (f32, f32) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// Write the stream header
+pub fn write_header(
+    writer: &mut impl Write,
+    (width, height): (u32, u32),
+    fps: u32,
+    chroma: Chroma,
+) -> Result<()> {
+    writeln!(
+        writer,
+        "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C{} XCOLORRANGE=LIMITED",
+        width,
+        height,
+        fps,
+        chroma.tag()
+    )?;
+    Ok(())
+}
+
+/// Convert a frame to limited range YUV and write its planes. Alpha is ignored.
+pub fn write_frame(
+    writer: &mut impl Write,
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    chroma: Chroma,
+    matrix: Matrix,
+) -> Result<()> {
+    let (kr, kb) = matrix.coefficients();
+    let kg = 1.0 - kr - kb;
+    let (width, height) = image.dimensions();
+
+    // Luma and color differences in 0-1 and -0.5-0.5 for each pixel
+    let yuv: Vec<[f32; 3]> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, _] = pixel.0.map(|it| it as f32 / 255.0);
+            let y = kr * r + kg * g + kb * b;
+            [
+                y,
+                (b - y) / (2.0 * (1.0 - kb)),
+                (r - y) / (2.0 * (1.0 - kr)),
+            ]
+        })
+        .collect();
+
+    let luma: Vec<u8> = yuv
+        .iter()
+        .map(|[y, _, _]| (16.0 + 219.0 * y).round() as u8)
+        .collect();
+
+    // Chroma samples are the average of the pixels of their block
+    let (block_width, block_height) = chroma.block();
+    let (chroma_width, chroma_height) =
+        (width.div_ceil(block_width), height.div_ceil(block_height));
+    let mut cb = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    let mut cr = Vec::with_capacity(cb.capacity());
+    for cy in 0..chroma_height {
+        for cx in 0..chroma_width {
+            let (mut sum, mut count) = ([0.0, 0.0], 0.0);
+            for y in cy * block_height..((cy + 1) * block_height).min(height) {
+                for x in cx * block_width..((cx + 1) * block_width).min(width) {
+                    let [_, u, v] = yuv[(y * width + x) as usize];
+                    sum[0] += u;
+                    sum[1] += v;
+                    count += 1.0;
+                }
+            }
+            cb.push((128.0 + 224.0 * sum[0] / count).round() as u8);
+            cr.push((128.0 + 224.0 * sum[1] / count).round() as u8);
+        }
+    }
+
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&luma)?;
+    writer.write_all(&cb)?;
+    writer.write_all(&cr)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    /// Luma, Cb and Cr planes of a frame
+    fn planes(
+        image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        chroma: Chroma,
+    ) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let mut frame = Vec::new();
+        write_frame(&mut frame, image, chroma, Matrix::Bt601).unwrap();
+        let data = frame.strip_prefix(b"FRAME\n").unwrap();
+        let luma = (image.width() * image.height()) as usize;
+        let chroma = (data.len() - luma) / 2;
+        (
+            data[..luma].to_vec(),
+            data[luma..luma + chroma].to_vec(),
+            data[luma + chroma..].to_vec(),
+        )
+    }
+
+    #[test]
+    fn limited_range_colors() {
+        let image = ImageBuffer::from_fn(2, 1, |x, _| if x == 0 { RED } else { WHITE });
+        let (luma, cb, cr) = planes(&image, Chroma::C444);
+        assert_eq!(luma, vec![81, 235]);
+        assert_eq!(cb, vec![90, 128]);
+        assert_eq!(cr, vec![240, 128]);
+    }
+
+    #[test]
+    fn chroma_is_averaged_over_blocks() {
+        // Red on the left half, white on the right half
+        let image = ImageBuffer::from_fn(4, 2, |x, _| if x < 2 { RED } else { WHITE });
+        let (luma, _, cr) = planes(&image, Chroma::C420);
+        assert_eq!(luma.len(), 8);
+        assert_eq!(cr, vec![240, 128]);
+
+        // Red on the first column only, each 2x1 block mixes red and white
+        let image = ImageBuffer::from_fn(2, 2, |x, _| if x == 0 { RED } else { WHITE });
+        let (_, _, cr) = planes(&image, Chroma::C422);
+        assert_eq!(cr, vec![184, 184]);
+    }
+
+    #[test]
+    fn odd_sizes_round_the_chroma_planes_up() {
+        let image = ImageBuffer::from_pixel(3, 3, RED);
+        let (luma, cb, cr) = planes(&image, Chroma::C420);
+        assert_eq!(luma.len(), 9);
+        assert_eq!(cb.len(), 4);
+        // Partial blocks average the pixels they cover
+        assert_eq!(cr, vec![240; 4]);
+        let (_, cb, _) = planes(&image, Chroma::C422);
+        assert_eq!(cb.len(), 6);
+    }
+}
@@ -0,0 +1,231 @@
+//! Analysis of audio files bound to channels.
+//!
+//! Like the audio channels of Shadertoy, the spectrum and the waveform at the current time are
+//! uploaded in a 512x2 texture : the first row is the spectrum from 0 to a quarter of the sample
+//! rate, the second row is the waveform. Nothing is played, the analysis only depends on the time.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+/// Width of the audio texture
+pub const AUDIO_TEXTURE_WIDTH: u32 = 512;
+/// Number of samples the spectrum is computed from
+const FFT_SIZE: usize = 4 * AUDIO_TEXTURE_WIDTH as usize;
+/// Range of the spectrum mapped to 0-1, in decibels
+const MIN_DECIBELS: f32 = -100.0;
+const MAX_DECIBELS: f32 = -30.0;
+/// Upper frequency of the bass and mid bands in Hz, the treble band is above
+const BASS_MAX: f32 = 250.0;
+const MID_MAX: f32 = 4000.0;
+
+/// The analysis of an audio file at a given time
+pub struct AudioFrame {
+    /// Spectrum and waveform rows of the audio texture, one byte per texel
+    pub texture: Vec<u8>,
+    /// Levels of the bass, mid and treble bands in 0-1
+    pub levels: [f32; 3],
+}
+
+/// A decoded audio file, mixed to mono
+pub struct Audio {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+}
+
+impl Audio {
+    /// Decode a WAV or FLAC file
+    pub fn load(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|it| it.to_str())
+            .map(|it| it.to_lowercase());
+        let (samples, sample_rate) = match extension.as_deref() {
+            Some("wav") => read_wav(path),
+            Some("flac") => read_flac(path),
+            _ => Err(anyhow!("Unsupported audio format, use WAV or FLAC")),
+        }
+        .with_context(|| format!("Can't load audio {}", path.display()))?;
+        Ok(Self {
+            samples,
+            sample_rate,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+        })
+    }
+
+    /// Duration of the file in seconds
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    /// Analyze the samples played just before the given time, silence is outside of the file
+    pub fn analyze(&self, time: f32) -> AudioFrame {
+        let end = (time.max(0.0) * self.sample_rate as f32) as usize;
+        let sample = |i: usize| {
+            (end + i)
+                .checked_sub(FFT_SIZE)
+                .and_then(|it| self.samples.get(it))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        // Blackman window, like the web audio analyser
+        let mut buffer: Vec<Complex<f32>> = (0..FFT_SIZE)
+            .map(|i| {
+                let x = i as f32 / FFT_SIZE as f32 * std::f32::consts::TAU;
+                let window = 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos();
+                Complex::new(sample(i) * window, 0.0)
+            })
+            .collect();
+        self.fft.process(&mut buffer);
+        let spectrum: Vec<f32> = buffer[..FFT_SIZE / 2]
+            .iter()
+            .map(|bin| {
+                let decibels = 20.0 * (bin.norm() / FFT_SIZE as f32).max(1e-10).log10();
+                ((decibels - MIN_DECIBELS) / (MAX_DECIBELS - MIN_DECIBELS)).clamp(0.0, 1.0)
+            })
+            .collect();
+
+        let width = AUDIO_TEXTURE_WIDTH as usize;
+        let mut texture = Vec::with_capacity(2 * width);
+        texture.extend(spectrum[..width].iter().map(|it| (it * 255.0) as u8));
+        texture.extend(
+            (FFT_SIZE - width..FFT_SIZE)
+                .map(|i| ((sample(i) * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0) as u8),
+        );
+
+        let bin_frequency = self.sample_rate as f32 / FFT_SIZE as f32;
+        let band_level = |min: f32, max: f32| {
+            let end = ((max / bin_frequency) as usize).min(spectrum.len());
+            let bins = &spectrum[((min / bin_frequency) as usize).min(end)..end];
+            bins.iter().sum::<f32>() / bins.len().max(1) as f32
+        };
+        AudioFrame {
+            texture,
+            levels: [
+                band_level(0.0, BASS_MAX),
+                band_level(BASS_MAX, MID_MAX),
+                band_level(MID_MAX, self.sample_rate as f32 / 2.0),
+            ],
+        }
+    }
+}
+
+/// Mix interleaved samples to mono
+fn mix(samples: &[f32], channels: usize) -> Vec<f32> {
+    samples
+        .chunks(channels.max(1))
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+fn read_wav(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|it| it.map(|it| it as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok((mix(&samples, spec.channels as usize), spec.sample_rate))
+}
+
+fn read_flac(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let mut reader = claxon::FlacReader::open(path)?;
+    let info = reader.streaminfo();
+    let scale = 1.0 / (1u32 << (info.bits_per_sample - 1)) as f32;
+    let samples: Vec<f32> = reader
+        .samples()
+        .map(|it| it.map(|it| it as f32 * scale))
+        .collect::<Result<_, _>>()?;
+    let samples = mix(&samples, info.channels as usize);
+    Ok((samples, info.sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+    use std::fs;
+
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// One second of a sine wave at the given frequency
+    fn sine(frequency: f32) -> Audio {
+        Audio {
+            samples: (0..SAMPLE_RATE)
+                .map(|i| (i as f32 / SAMPLE_RATE as f32 * frequency * TAU).sin())
+                .collect(),
+            sample_rate: SAMPLE_RATE,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+        }
+    }
+
+    /// Index of the loudest band
+    fn loudest(frequency: f32) -> usize {
+        let [bass, mid, treble] = sine(frequency).analyze(0.5).levels;
+        if bass > mid && bass > treble {
+            0
+        } else if mid > treble {
+            1
+        } else {
+            2
+        }
+    }
+
+    #[test]
+    fn sine_levels_are_in_their_band() {
+        assert_eq!(loudest(100.0), 0);
+        assert_eq!(loudest(1000.0), 1);
+        assert_eq!(loudest(10000.0), 2);
+    }
+
+    #[test]
+    fn silence_before_the_start() {
+        let frame = sine(440.0).analyze(0.0);
+        assert_eq!(frame.levels, [0.0; 3]);
+        assert_eq!(frame.texture.len(), 2 * AUDIO_TEXTURE_WIDTH as usize);
+        // The waveform is centered
+        assert!(frame.texture[AUDIO_TEXTURE_WIDTH as usize..]
+            .iter()
+            .all(|it| *it == 127));
+    }
+
+    #[test]
+    fn truncated_wav_is_an_error() {
+        let path = std::env::temp_dir().join(format!("nuance_audio_{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..100 {
+            writer.write_sample(i16::MAX / 2).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (samples, sample_rate) = read_wav(&path).unwrap();
+        assert_eq!((samples.len(), sample_rate), (100, SAMPLE_RATE));
+        assert!((samples[0] - 0.25).abs() < 1e-3);
+
+        // Cut in the middle of a sample
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let read = read_wav(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(read.is_err());
+    }
+}
@@ -0,0 +1,21 @@
+// Channels, bound to textures or to the output of passes
+
+#ifndef NUANCE_CHANNELS
+#define NUANCE_CHANNELS
+
+layout(set = 2, binding = 0) uniform texture2D nuanceChannel0;
+layout(set = 2, binding = 1) uniform sampler nuanceChannelSampler0;
+layout(set = 2, binding = 2) uniform texture2D nuanceChannel1;
+layout(set = 2, binding = 3) uniform sampler nuanceChannelSampler1;
+layout(set = 2, binding = 4) uniform texture2D nuanceChannel2;
+layout(set = 2, binding = 5) uniform sampler nuanceChannelSampler2;
+layout(set = 2, binding = 6) uniform texture2D nuanceChannel3;
+layout(set = 2, binding = 7) uniform sampler nuanceChannelSampler3;
+
+// Sample a channel like a sampler2D
+#define uChannel0 sampler2D(nuanceChannel0, nuanceChannelSampler0)
+#define uChannel1 sampler2D(nuanceChannel1, nuanceChannelSampler1)
+#define uChannel2 sampler2D(nuanceChannel2, nuanceChannelSampler2)
+#define uChannel3 sampler2D(nuanceChannel3, nuanceChannelSampler3)
+
+#endif
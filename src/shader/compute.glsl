@@ -0,0 +1,52 @@
+#define NUANCE
+
+#version 460
+
+#include <noise>
+
+// Each invocation computes a pixel, workgroups of 8x8 pixels cover the canvas
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// Current pixel coordinates
+#define pixelCoord ivec2(gl_GlobalInvocationID.xy)
+// Current pixel center in normalized space
+#define pixelCoordNorm ((vec2(pixelCoord) + 0.5) / uResolution)
+
+#include <NuanceGlobals>
+
+layout(set = 0, binding = 0) uniform texture2D lastFrame;
+layout(set = 0, binding = 1) uniform sampler lastFrameSampler;
+// The image displayed on the canvas, its format is set by NUANCE_TARGET_FORMAT
+layout(set = 0, binding = 2, NUANCE_IMAGE_FORMAT) uniform writeonly image2D outputImage;
+
+// Channels, sampled with uChannel0 to uChannel3 and textureLod
+#include <NuanceChannels>
+
+#define FIRST_RUN uFrame == 0
+
+// Write the color of a pixel, pixels outside of the canvas are ignored
+void writeOutput(ivec2 xy, vec4 color) {
+    if (all(lessThan(xy, ivec2(uResolution)))) {
+        imageStore(outputImage, xy, color);
+    }
+}
+
+// Write the color of the current pixel
+void writeOutput(vec4 color) {
+    writeOutput(pixelCoord, color);
+}
+
+// Sample the last frame at the given normalized coordinates
+vec4 samplePreviousN(vec2 st) {
+    return textureLod(sampler2D(lastFrame, lastFrameSampler), st, 0.0);
+}
+
+// Sample last frame at the given coordinates in pixel coordinates
+vec4 samplePrevious(vec2 xy) {
+    return samplePreviousN(xy / uResolution);
+}
+
+// Sample last frame at the current pixel
+vec4 samplePrevious() {
+    return samplePreviousN(pixelCoordNorm);
+}
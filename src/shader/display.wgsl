@@ -0,0 +1,44 @@
+// Converts the output of the displayed pass to the surface format, which encodes to sRGB
+
+struct Display {
+    // 0 : none, 1 : Reinhard, 2 : ACES
+    tonemap: u32;
+    clamp_output: u32;
+    // Decode colors so they are stored as written
+    passthrough: u32;
+};
+
+[[group(0), binding(0)]]
+var input: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var<uniform> display: Display;
+
+// Fit of the ACES filmic curve by Krzysztof Narkowicz
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / 12.92;
+    let higher = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+[[stage(fragment)]]
+fn main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    var color = textureLoad(input, vec2<i32>(position.xy), 0);
+    if (display.tonemap == 1u) {
+        color = vec4<f32>(color.rgb / (color.rgb + vec3<f32>(1.0)), color.a);
+    } else if (display.tonemap == 2u) {
+        color = vec4<f32>(aces(color.rgb), color.a);
+    }
+    if (display.clamp_output != 0u) {
+        color = clamp(color, vec4<f32>(0.0), vec4<f32>(1.0));
+    }
+    if (display.passthrough != 0u) {
+        color = vec4<f32>(to_linear(max(color.rgb, vec3<f32>(0.0))), color.a);
+    }
+    return color;
+}
@@ -0,0 +1,32 @@
+#ifndef NUANCE_GLOBALS
+#define NUANCE_GLOBALS
+
+// Globals are variables your shader can access
+layout(push_constant) uniform Globals {
+// Window resolution
+    uvec2 uResolution;
+// Mouse position
+    uvec2 uMouse;
+// Mouse wheel
+    float fMouseWheel;
+// Aspect ratio
+    float fRatio;
+// Time in sec
+    float fTime;
+// The number of frame we're at
+    uint uFrame;
+// Time since the last frame in sec
+    float fTimeDelta;
+// Date (UTC) : year, month (0-11), day, seconds since midnight
+    vec4 vDate;
+// Levels of the bass, mid and treble bands of the first audio channel (0-1)
+    float fBass;
+    float fMid;
+    float fTreble;
+#ifdef NUANCE_VERTEX_GLOBALS
+// Number of vertices drawn by a vertex shader, after the globals
+    layout(offset = NUANCE_VERTEX_COUNT_OFFSET) uint uVertexCount;
+#endif
+};
+
+#endif
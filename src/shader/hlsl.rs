@@ -0,0 +1,40 @@
+//! Extract information from hlsl source.
+//!
+//! Hlsl is compiled to spirv first, the module interface is then checked like any other spirv
+//! module. Params are declared with a cbuffer bound at `[[vk::binding(0, 1)]]`, each member can be
+//! annotated in a comment on the same line or on the lines before.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::debug;
+use naga::Module;
+
+use crate::shader::reflection::{self, Annotation};
+use crate::shader::spirv;
+use crate::ShaderMetadata;
+
+/// Check the compiled module interface and extract params from it
+pub fn extract(source: &str, module: &Module) -> Result<ShaderMetadata> {
+    let annotations = reflection::find_params_block(module)
+        .and_then(|params| module.types[params.ty].name.as_ref())
+        .map(|name| member_annotations(source, name))
+        .unwrap_or_default();
+    debug!("params annotations : {:?}", annotations);
+    spirv::extract(module, &annotations)
+}
+
+/// Collect annotations on the members of the cbuffer with the given name
+pub fn member_annotations(source: &str, cbuffer_name: &str) -> HashMap<String, Vec<Annotation>> {
+    reflection::find_block_body(source, "cbuffer", cbuffer_name)
+        .map(|body| {
+            reflection::member_annotations(body, |code| {
+                // Members are declared as `type name : packoffset(...);`
+                let (decl, _) = code.split_once(';')?;
+                let decl = decl.split(':').next()?;
+                let decl = decl.split('[').next()?;
+                decl.split_whitespace().last()
+            })
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,9 @@
+// The interface between Nuance and your shader
+
+// Current fragment output color
+layout(location = 0) out vec4 fragColor;
+
+layout(set = 0, binding = 0) uniform texture2D lastFrame;
+layout(set = 0, binding = 1) uniform sampler lastFrameSampler;
+
+#include <NuanceGlobals>
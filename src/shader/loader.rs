@@ -1,21 +1,38 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use log::{debug, warn};
+use mint::Vector2;
 use shaderc::{
     CompileOptions, Compiler, EnvVersion, GlslProfile, IncludeType, OptimizationLevel,
     ResolvedInclude, ShaderKind, SourceLanguage, TargetEnv,
 };
 use wgpu::ShaderSource;
 
-use crate::shader::preprocessor;
-use crate::shader::Shader;
+use crate::renderer::{self, CHANNEL_COUNT};
+use crate::shader::project::Manifest;
+use crate::shader::{hlsl, preprocessor, reflection, shadertoy, spirv, wgsl};
+use crate::shader::{
+    BufferInput, Channel, ChannelSampler, Pass, Shader, ShaderMetadata, TargetFormat, TextureFile,
+};
+
+/// Fragment stage of the vertex shaders not declaring theirs, colors primitives with `vColor`
+const VERTEX_COLOR_FRAGMENT: &str = "#include <NuanceVertex>
+
+void main() {
+    fragColor = vColor;
+}
+";
 
 pub struct ShaderLoader {
     compiler: Compiler,
     include_dirs: Vec<String>,
+    /// Files included by the shaders compiled since the last load
+    included: Vec<PathBuf>,
 }
 
 impl Default for ShaderLoader {
@@ -23,6 +40,7 @@ impl Default for ShaderLoader {
         ShaderLoader {
             compiler: Compiler::new().expect("Can't create compiler"),
             include_dirs: Vec::with_capacity(4),
+            included: Vec::new(),
         }
     }
 }
@@ -33,89 +51,570 @@ impl ShaderLoader {
     }
 
     pub fn supported_extensions() -> &'static [&'static str] {
-        &["glsl", "frag", "wgsl", "spv"]
+        &[
+            "glsl", "frag", "comp", "vert", "wgsl", "spv", "hlsl", "json", "toml",
+        ]
     }
 
     pub fn add_include_dir(&mut self, include: &str) {
         self.include_dirs.push(include.to_string());
     }
 
-    /// Load a shader, this will try to guess its type based on the file extension
-    pub fn load_shader<P: AsRef<Path>>(&mut self, path: P) -> Result<(Shader, ShaderSource)> {
+    /// Load a shader or a project, this will try to guess its type based on the file extension.
+    /// Returns the compiled source of each pass of the shader.
+    pub fn load_shader<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<(Shader, Vec<ShaderSource<'static>>)> {
         let path = path.as_ref();
-        // TODO collect all files necessary to compilation for watch
+        self.included.clear();
+        let (mut shader, sources) = match path.extension().and_then(|it| it.to_str()) {
+            Some("toml") => self.load_project(path)?,
+            _ => self.load_file(path)?,
+        };
+        // Watch included files too
+        for file in self.included.drain(..) {
+            if !shader.sources.contains(&file) {
+                shader.sources.push(file);
+            }
+        }
+        Ok((shader, sources))
+    }
+
+    /// Load a shader file
+    fn load_file(&mut self, path: &Path) -> Result<(Shader, Vec<ShaderSource<'static>>)> {
         match path.extension().and_then(|it| it.to_str()) {
             Some("spv") => {
-                // sry for that terrible thing
-                let data: Vec<u32> = fs::read(path)?.into_iter().map(|i| i as u32).collect();
-                // We can't extract metadata from spirv modules
+                let data = spirv::read_words(&fs::read(path)?)?;
+                let module = spirv::parse(&data)?;
+                // Check the module interface and rebuild params from debug names
+                let metadata = spirv::extract(&module, &HashMap::new())?;
+                Ok((
+                    Shader::new(
+                        path,
+                        Some(metadata),
+                        reflection::fragment_entry_points(&module),
+                    )?,
+                    vec![ShaderSource::SpirV(Cow::Owned(data))],
+                ))
+            }
+            Some("glsl") | Some("frag") => self.load_glsl(path),
+            Some("comp") => self.load_compute(path),
+            Some("vert") => self.load_vertex(path),
+            Some("hlsl") => {
+                let source = fs::read_to_string(path)?;
+                let compiled = self.compile_shader(
+                    path.to_str().unwrap(),
+                    &source,
+                    "main",
+                    SourceLanguage::HLSL,
+                )?;
+                // Check the compiled interface and extract params
+                let module = match &compiled {
+                    ShaderSource::SpirV(data) => spirv::parse(data)?,
+                    _ => unreachable!("shaderc always outputs spirv"),
+                };
+                let metadata = hlsl::extract(&source, &module)?;
+                Ok((
+                    Shader::new(
+                        path,
+                        Some(metadata),
+                        reflection::fragment_entry_points(&module),
+                    )?,
+                    vec![compiled],
+                ))
+            }
+            Some("wgsl") => {
+                let source = fs::read_to_string(path)?;
+                let module = wgsl::parse(&source)?;
+                let metadata = wgsl::extract(&source, &module)?;
                 Ok((
-                    Shader {
-                        main: path.to_path_buf(),
-                        sources: vec![path.to_path_buf()],
-                        metadata: None,
-                    },
-                    ShaderSource::SpirV(Cow::Owned(data)),
+                    Shader::new(
+                        path,
+                        Some(metadata),
+                        reflection::fragment_entry_points(&module),
+                    )?,
+                    vec![ShaderSource::Wgsl(Cow::Owned(source))],
                 ))
             }
-            Some("glsl") | Some("frag") => {
-                // Preprocess glsl to extract what we need
-                let mut source = fs::read_to_string(path)?;
-                debug!("{}", &source);
-                let (metadata, new) = preprocessor::extract(&source)?;
-                source = new;
-
-                self.compile_shader(path.to_str().unwrap(), &source, "main")
-                    .map(|it| {
-                        (
-                            Shader {
-                                main: path.to_path_buf(),
-                                sources: vec![path.to_path_buf()],
-                                metadata: Some(metadata),
-                            },
-                            it,
+            Some("json") => {
+                // A Shadertoy export, each pass is compiled like a glsl shader
+                let passes = shadertoy::import(&fs::read_to_string(path)?)?;
+                let mut sources = Vec::with_capacity(passes.len());
+                let mut metadata = None;
+                for pass in passes.iter() {
+                    let (pass_metadata, source) = preprocessor::extract(&pass.code)
+                        .with_context(|| format!("Can't preprocess pass {}", pass.name))?;
+                    if !pass_metadata.shadertoy {
+                        return Err(anyhow!("Pass {} has no mainImage function", pass.name));
+                    }
+                    sources.push(
+                        self.compile_shader(
+                            path.to_str().unwrap(),
+                            &source,
+                            "main",
+                            SourceLanguage::GLSL,
                         )
+                        .with_context(|| format!("Can't compile pass {}", pass.name))?,
+                    );
+                    // The displayed pass is the last one
+                    metadata = Some(pass_metadata);
+                }
+
+                let mut shader = Shader::new(path, metadata, vec!["main".to_string()])?;
+                shader.passes = passes
+                    .into_iter()
+                    .map(|it| Pass {
+                        name: it.name,
+                        entry_point: "main".to_string(),
+                        channels: it.channels,
+                        samplers: it.samplers,
+                        previous: Default::default(),
+                        textures: Vec::new(),
                     })
+                    .collect();
+                Ok((shader, sources))
             }
-            Some("wgsl") => Ok((
-                // TODO extract data from wgsl
-                Shader {
-                    main: path.to_path_buf(),
-                    sources: vec![path.to_path_buf()],
-                    metadata: None,
-                },
-                ShaderSource::Wgsl(Cow::Owned(fs::read_to_string(path)?)),
-            )),
             _ => Err(anyhow!("Unsupported shader format !")),
         }
     }
 
-    /// Compile a shader from source to spirv in memory
+    /// Load the passes of a project, each pass is a single shader file
+    fn load_project(&mut self, path: &Path) -> Result<(Shader, Vec<ShaderSource<'static>>)> {
+        let manifest = Manifest::read(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        // Include dirs of the project are only used for this project
+        let include_dirs = self.include_dirs.clone();
+        self.include_dirs.extend(
+            manifest
+                .include_dirs
+                .iter()
+                .map(|it| dir.join(it).to_string_lossy().into_owned()),
+        );
+        let loaded = self.load_project_passes(&manifest, dir);
+        self.include_dirs = include_dirs;
+        let (passes, sources, files, displayed) = loaded?;
+
+        let (mut metadata, entry_points) = displayed;
+        if let Some(metadata) = metadata.as_mut() {
+            for (name, value) in manifest.params.iter() {
+                match metadata.sliders.iter_mut().find(|it| it.name() == name) {
+                    Some(slider) => slider.set_default(&value.values())?,
+                    None => warn!("No param named {} in the displayed pass", name),
+                }
+            }
+        }
+
+        let mut shader = Shader::new(path, metadata, entry_points)?;
+        for file in files {
+            if !shader.sources.contains(&file) {
+                shader.sources.push(file);
+            }
+        }
+        shader.passes = passes;
+        shader.resolution = manifest.resolution.map(Vector2::from);
+        shader.framerate = manifest.framerate;
+        Ok((shader, sources))
+    }
+
+    /// Load the shader of each pass and bind their channels.
+    /// Returns the passes, their sources, the files they use and the displayed pass metadata.
+    #[allow(clippy::type_complexity)]
+    fn load_project_passes(
+        &mut self,
+        manifest: &Manifest,
+        dir: &Path,
+    ) -> Result<(
+        Vec<Pass>,
+        Vec<ShaderSource<'static>>,
+        Vec<PathBuf>,
+        (Option<ShaderMetadata>, Vec<String>),
+    )> {
+        let names: Vec<String> = manifest
+            .passes
+            .iter()
+            .map(|it| it.name.clone().unwrap_or_else(|| pass_name(&it.shader)))
+            .collect();
+
+        let mut passes = Vec::with_capacity(manifest.passes.len());
+        let mut sources = Vec::with_capacity(manifest.passes.len());
+        let mut files = Vec::new();
+        let mut displayed = (None, Vec::new());
+        for (i, decl) in manifest.passes.iter().enumerate() {
+            let (shader, mut pass_sources) = self
+                .load_file(&dir.join(&decl.shader))
+                .with_context(|| format!("Can't load pass {}", names[i]))?;
+            if pass_sources.len() > 1
+                || shader
+                    .metadata
+                    .as_ref()
+                    .is_some_and(|it| !it.buffers.is_empty())
+            {
+                return Err(anyhow!(
+                    "Pass {} must be a single pass shader, declare its channels in the manifest",
+                    names[i]
+                ));
+            }
+            if i + 1 < manifest.passes.len() {
+                check_hidden_pass(&names[i], shader.metadata.as_ref())?;
+            }
+            sources.push(pass_sources.remove(0));
+            files.extend(shader.sources.iter().cloned());
+
+            let mut pass = shader.passes.into_iter().next().unwrap();
+            pass.name = names[i].clone();
+            if let Some(entry_point) = &decl.entry_point {
+                if !shader.entry_points.contains(entry_point) {
+                    return Err(anyhow!(
+                        "Pass {} has no entry point named {}",
+                        names[i],
+                        entry_point
+                    ));
+                }
+                pass.entry_point = entry_point.clone();
+            }
+
+            if decl.channels.len() > CHANNEL_COUNT {
+                return Err(anyhow!(
+                    "Pass {} has more than {} channels",
+                    names[i],
+                    CHANNEL_COUNT
+                ));
+            }
+            for (sampler, input) in pass.samplers.iter_mut().zip(decl.channels.iter()) {
+                *sampler = ChannelSampler {
+                    filter: input.filter,
+                    wrap: input.wrap,
+                    mipmaps: input.mipmaps,
+                };
+            }
+            for (channel, input) in pass.channels.iter_mut().zip(decl.channels.iter()) {
+                *channel = match (&input.pass, &input.texture, &input.audio) {
+                    (Some(name), None, None) => {
+                        let index = names.iter().position(|it| it == name).ok_or_else(|| {
+                            anyhow!("Pass {} samples an unknown pass {}", names[i], name)
+                        })?;
+                        if index >= i && !input.previous {
+                            return Err(anyhow!(
+                                "Pass {} samples the current output of pass {} which is not rendered before it, sample its previous output",
+                                names[i],
+                                name
+                            ));
+                        }
+                        Channel::Pass {
+                            index,
+                            previous: input.previous,
+                        }
+                    }
+                    (None, Some(texture), None) => {
+                        let texture = dir.join(texture);
+                        files.push(texture.clone());
+                        Channel::Texture(texture)
+                    }
+                    (None, None, Some(audio)) => {
+                        let audio = dir.join(audio);
+                        files.push(audio.clone());
+                        Channel::Audio(audio)
+                    }
+                    (None, None, None) => Channel::Empty,
+                    _ => {
+                        return Err(anyhow!(
+                            "A channel of pass {} can only be bound to one of a pass, a texture or an audio file",
+                            names[i]
+                        ))
+                    }
+                };
+            }
+            passes.push(pass);
+            displayed = (shader.metadata, shader.entry_points);
+        }
+        Ok((passes, sources, files, displayed))
+    }
+
+    /// Load a glsl shader along with the passes it samples, which are searched in the same
+    /// directory as `<name>.frag` or `<name>.glsl`
+    fn load_glsl(&mut self, path: &Path) -> Result<(Shader, Vec<ShaderSource<'static>>)> {
+        // Preprocess the shader and all the passes it depends on, the shader comes first
+        let mut files = vec![(pass_name(path), path.to_path_buf())];
+        let mut preprocessed: Vec<(ShaderMetadata, String)> = Vec::new();
+        while let Some((name, file)) = files.get(preprocessed.len()).cloned() {
+            // Preprocess glsl to extract what we need
+            let source = fs::read_to_string(&file)?;
+            debug!("{}", &source);
+            let (metadata, source) = preprocessor::extract(&source)
+                .with_context(|| format!("Can't preprocess pass {}", name))?;
+            if metadata.sound {
+                return Err(anyhow!(
+                    "Pass {} is a sound shader, it can only be rendered to a sound file",
+                    name
+                ));
+            }
+            for buffer in metadata.buffers.iter() {
+                if !files.iter().any(|(name, _)| *name == buffer.pass) {
+                    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+                    files.push((buffer.pass.clone(), find_pass(dir, &buffer.pass)?));
+                }
+            }
+            preprocessed.push((metadata, source));
+        }
+
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        let inputs: Vec<&[BufferInput]> = preprocessed
+            .iter()
+            .map(|(metadata, _)| metadata.buffers.as_slice())
+            .collect();
+        let order = order_passes(&names, &inputs)?;
+
+        let mut sources = Vec::with_capacity(order.len());
+        let mut passes = Vec::with_capacity(order.len());
+        let mut textures = Vec::new();
+        for &i in order.iter() {
+            let (metadata, source) = &preprocessed[i];
+            if i != 0 {
+                check_hidden_pass(names[i], Some(metadata))?;
+            }
+            sources.push(
+                self.compile_shader(
+                    files[i].1.to_str().unwrap(),
+                    source,
+                    "main",
+                    SourceLanguage::GLSL,
+                )
+                .with_context(|| format!("Can't compile pass {}", names[i]))?,
+            );
+
+            let mut channels: [Channel; CHANNEL_COUNT] = Default::default();
+            for (channel, buffer) in channels.iter_mut().zip(metadata.buffers.iter()) {
+                *channel = Channel::Pass {
+                    index: order
+                        .iter()
+                        .position(|&it| names[it] == buffer.pass)
+                        .unwrap(),
+                    previous: buffer.previous,
+                };
+            }
+            // Textures are relative to the shader declaring them
+            let dir = files[i].1.parent().unwrap_or_else(|| Path::new("."));
+            let pass_textures: Vec<TextureFile> = metadata
+                .textures
+                .iter()
+                .map(|it| it.file.joined(dir))
+                .collect();
+            textures.extend(pass_textures.iter().map(|it| it.path().to_path_buf()));
+            passes.push(Pass {
+                name: names[i].to_string(),
+                // There can only be one entry point in glsl
+                entry_point: "main".to_string(),
+                channels,
+                samplers: Default::default(),
+                previous: metadata.previous,
+                textures: pass_textures,
+            });
+        }
+
+        let metadata = preprocessed.swap_remove(0).0;
+        let mut shader = Shader::new(path, Some(metadata), vec!["main".to_string()])?;
+        shader.sources = files.into_iter().map(|(_, file)| file).collect();
+        // Watch textures to reload them
+        for texture in textures {
+            if !shader.sources.contains(&texture) {
+                shader.sources.push(texture);
+            }
+        }
+        shader.passes = passes;
+        Ok((shader, sources))
+    }
+
+    /// Load a glsl compute shader, it is a single pass writing to the displayed image
+    fn load_compute(&mut self, path: &Path) -> Result<(Shader, Vec<ShaderSource<'static>>)> {
+        let source = fs::read_to_string(path)?;
+        let (metadata, source) = preprocessor::extract(&source)?;
+        if !metadata.buffers.is_empty() {
+            return Err(anyhow!("Compute shaders can't sample other passes"));
+        }
+        if metadata.shadertoy {
+            return Err(anyhow!("Compute shaders can't be Shadertoy shaders"));
+        }
+        // The output image is declared with the format of the target
+        let image_format = match metadata.target_format.unwrap_or_default() {
+            TargetFormat::Surface => "rgba8",
+            TargetFormat::Rgba16Float => "rgba16f",
+            TargetFormat::Rgba32Float => "rgba32f",
+        };
+        let compiled = self.compile(
+            path.to_str().unwrap(),
+            &source,
+            "main",
+            SourceLanguage::GLSL,
+            ShaderKind::Compute,
+            &[("NUANCE_IMAGE_FORMAT", image_format)],
+        )?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let textures: Vec<TextureFile> = metadata
+            .textures
+            .iter()
+            .map(|it| it.file.joined(dir))
+            .collect();
+        let mut shader = Shader::new(path, Some(metadata), vec!["main".to_string()])?;
+        shader.kind = ShaderKind::Compute;
+        // Watch textures to reload them
+        shader
+            .sources
+            .extend(textures.iter().map(|it| it.path().to_path_buf()));
+        shader.passes[0].textures = textures;
+        Ok((shader, vec![compiled]))
+    }
+
+    /// Load a glsl vertex shader, a single pass drawing primitives.
+    /// Returns the compiled vertex stage then the fragment stage.
+    fn load_vertex(&mut self, path: &Path) -> Result<(Shader, Vec<ShaderSource<'static>>)> {
+        let source = fs::read_to_string(path)?;
+        let (metadata, source) = preprocessor::extract(&source)?;
+        if !metadata.buffers.is_empty() {
+            return Err(anyhow!("Vertex shaders can't sample other passes"));
+        }
+        if metadata.shadertoy {
+            return Err(anyhow!("Vertex shaders can't be Shadertoy shaders"));
+        }
+        if metadata.output.is_some() {
+            return Err(anyhow!("Vertex shaders can't have outputs"));
+        }
+        let name = path.to_str().unwrap();
+        // The vertex count follows the globals in the push constants
+        let count_offset = renderer::vertex_count_offset().to_string();
+        let count_offset = ("NUANCE_VERTEX_COUNT_OFFSET", count_offset.as_str());
+        let vertex = self
+            .compile(
+                name,
+                &source,
+                "main",
+                SourceLanguage::GLSL,
+                ShaderKind::Vertex,
+                &[count_offset],
+            )
+            .context("Can't compile the vertex stage")?;
+        // The fragment stage is written in the same file, or only colors the primitives
+        let fragment_source = if source.contains("NUANCE_FRAGMENT") {
+            source.as_str()
+        } else {
+            VERTEX_COLOR_FRAGMENT
+        };
+        let fragment = self
+            .compile(
+                name,
+                fragment_source,
+                "main",
+                SourceLanguage::GLSL,
+                ShaderKind::Fragment,
+                &[("NUANCE_FRAGMENT", ""), count_offset],
+            )
+            .context("Can't compile the fragment stage")?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let textures: Vec<TextureFile> = metadata
+            .textures
+            .iter()
+            .map(|it| it.file.joined(dir))
+            .collect();
+        let mut shader = Shader::new(path, Some(metadata), vec!["main".to_string()])?;
+        shader.kind = ShaderKind::Vertex;
+        // Watch textures to reload them
+        shader
+            .sources
+            .extend(textures.iter().map(|it| it.path().to_path_buf()));
+        shader.passes[0].textures = textures;
+        Ok((shader, vec![vertex, fragment]))
+    }
+
+    /// Load a glsl sound shader declaring `mainSound`, it is compiled to a fragment shader writing
+    /// a stereo sample per texel
+    pub fn load_sound<P: AsRef<Path>>(&mut self, path: P) -> Result<ShaderSource<'static>> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let (metadata, source) = preprocessor::extract(&source)?;
+        if !metadata.sound {
+            return Err(anyhow!(
+                "{} has no vec2 mainSound(int, float) function",
+                path.display()
+            ));
+        }
+        if !metadata.sliders.is_empty()
+            || !metadata.buffers.is_empty()
+            || !metadata.textures.is_empty()
+            || metadata.output.is_some()
+        {
+            return Err(anyhow!(
+                "Sound shaders can't have params, sample passes, textures or outputs"
+            ));
+        }
+        self.compile_shader(
+            path.to_str().unwrap(),
+            &source,
+            "main",
+            SourceLanguage::GLSL,
+        )
+    }
+
+    /// Compile a fragment shader from source to spirv in memory
     pub fn compile_shader(
         &mut self,
         name: &str,
         source: &str,
         entrypoint: &str,
-    ) -> Result<ShaderSource<'_>> {
+        language: SourceLanguage,
+    ) -> Result<ShaderSource<'static>> {
+        self.compile(
+            name,
+            source,
+            entrypoint,
+            language,
+            ShaderKind::Fragment,
+            &[],
+        )
+    }
+
+    /// Compile a shader of any kind, with additional macro definitions
+    fn compile(
+        &mut self,
+        name: &str,
+        source: &str,
+        entrypoint: &str,
+        language: SourceLanguage,
+        kind: ShaderKind,
+        macros: &[(&str, &str)],
+    ) -> Result<ShaderSource<'static>> {
+        // Relative includes are files to watch
+        let included = RefCell::new(Vec::new());
         let mut opts = CompileOptions::new().unwrap();
-        opts.set_source_language(SourceLanguage::GLSL);
+        opts.set_source_language(language);
         opts.set_optimization_level(OptimizationLevel::Zero);
         opts.set_target_env(TargetEnv::Vulkan, EnvVersion::WebGPU as u32);
         //options.set_target_spirv(SpirvVersion::V1_5);
-        opts.set_forced_version_profile(460, GlslProfile::None);
+        if language == SourceLanguage::GLSL {
+            opts.set_forced_version_profile(460, GlslProfile::None);
+        }
+        for (name, value) in macros {
+            opts.add_macro_definition(name, Some(value));
+        }
 
         let include_dirs = &self.include_dirs;
-        opts.set_include_callback(move |name, include_type, source_file, _| {
-            Self::find_include(include_dirs, name, include_type, source_file)
+        opts.set_include_callback(|name, include_type, source_file, _| {
+            let resolved =
+                Self::find_include(include_dirs, name, include_type, source_file, language);
+            if let (Ok(resolved), IncludeType::Relative) = (&resolved, include_type) {
+                included
+                    .borrow_mut()
+                    .push(PathBuf::from(&resolved.resolved_name));
+            }
+            resolved
         });
 
-        let compiled = self.compiler.compile_into_spirv(
-            source,
-            ShaderKind::Fragment,
-            name,
-            entrypoint,
-            Some(&opts),
-        )?;
+        let compiled =
+            self.compiler
+                .compile_into_spirv(source, kind, name, entrypoint, Some(&opts))?;
+        self.included.extend(included.take());
 
         if compiled.get_num_warnings() > 0 {
             warn!(
@@ -135,6 +634,7 @@ impl ShaderLoader {
         name: &str,
         include_type: IncludeType,
         source_file: &str,
+        language: SourceLanguage,
     ) -> Result<ResolvedInclude, String> {
         match include_type {
             IncludeType::Relative => {
@@ -165,24 +665,218 @@ impl ShaderLoader {
             }
             IncludeType::Standard => {
                 // The nuance standard header
-                match name {
-                    "Nuance" => {
+                match (name, language) {
+                    ("Nuance", SourceLanguage::HLSL) => {
+                        const STD_NUANCE_HLSL: &str = include_str!("stdlib.hlsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_NUANCE_HLSL".to_owned(),
+                            content: STD_NUANCE_HLSL.to_owned(),
+                        })
+                    }
+                    ("noise", SourceLanguage::HLSL) => {
+                        const STD_NOISE_HLSL: &str = include_str!("noise.hlsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_NOISE_HLSL".to_owned(),
+                            content: STD_NOISE_HLSL.to_owned(),
+                        })
+                    }
+                    ("Nuance", _) => {
                         const STD_NUANCE: &str = include_str!("stdlib.glsl");
                         Ok(ResolvedInclude {
                             resolved_name: "STD_NUANCE".to_owned(),
                             content: STD_NUANCE.to_owned(),
                         })
                     }
-                    "noise" => {
+                    ("NuanceInterface", _) => {
+                        const STD_INTERFACE: &str = include_str!("interface.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_INTERFACE".to_owned(),
+                            content: STD_INTERFACE.to_owned(),
+                        })
+                    }
+                    ("NuanceGlobals", _) => {
+                        const STD_GLOBALS: &str = include_str!("globals.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_GLOBALS".to_owned(),
+                            content: STD_GLOBALS.to_owned(),
+                        })
+                    }
+                    ("NuanceVertex", _) => {
+                        const STD_VERTEX: &str = include_str!("vertex.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_VERTEX".to_owned(),
+                            content: STD_VERTEX.to_owned(),
+                        })
+                    }
+                    ("NuanceCompute", _) => {
+                        const STD_COMPUTE: &str = include_str!("compute.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_COMPUTE".to_owned(),
+                            content: STD_COMPUTE.to_owned(),
+                        })
+                    }
+                    ("NuanceChannels", _) => {
+                        const STD_CHANNELS: &str = include_str!("channels.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_CHANNELS".to_owned(),
+                            content: STD_CHANNELS.to_owned(),
+                        })
+                    }
+                    ("Shadertoy", _) => {
+                        const STD_SHADERTOY: &str = include_str!("shadertoy.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_SHADERTOY".to_owned(),
+                            content: STD_SHADERTOY.to_owned(),
+                        })
+                    }
+                    ("NuanceSound", _) => {
+                        const STD_SOUND: &str = include_str!("sound.glsl");
+                        Ok(ResolvedInclude {
+                            resolved_name: "STD_SOUND".to_owned(),
+                            content: STD_SOUND.to_owned(),
+                        })
+                    }
+                    ("noise", _) => {
                         const STD_NOISE: &str = include_str!("noise.glsl");
                         Ok(ResolvedInclude {
                             resolved_name: "STD_NOISE".to_owned(),
                             content: STD_NOISE.to_owned(),
                         })
                     }
-                    other => Err(format!("No standard include exist with name {}", other)),
+                    (other, _) => Err(format!("No standard include exist with name {}", other)),
                 }
             }
         }
     }
 }
+
+/// Passes are named after their file
+fn pass_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Find the file of a pass in a directory
+fn find_pass(dir: &Path, name: &str) -> Result<PathBuf> {
+    ["frag", "glsl"]
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            anyhow!(
+                "Can't find pass {} ({0}.frag or {0}.glsl) in {}",
+                name,
+                dir.display()
+            )
+        })
+}
+
+/// Only the displayed pass is bound to the params and the output buffer
+fn check_hidden_pass(name: &str, metadata: Option<&ShaderMetadata>) -> Result<()> {
+    if let Some(metadata) = metadata {
+        if !metadata.sliders.is_empty() {
+            return Err(anyhow!(
+                "Pass {} has params, only the displayed pass can have params",
+                name
+            ));
+        }
+        if metadata.output.is_some() {
+            return Err(anyhow!(
+                "Pass {} has an output block, only the displayed pass can have one",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Order the passes so each one is rendered after the passes whose current output it samples.
+/// The first pass is the displayed one, it must be rendered last.
+fn order_passes(names: &[&str], inputs: &[&[BufferInput]]) -> Result<Vec<usize>> {
+    fn visit(
+        i: usize,
+        names: &[&str],
+        inputs: &[&[BufferInput]],
+        visiting: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        if order.contains(&i) {
+            return Ok(());
+        }
+        if visiting.contains(&i) {
+            return Err(anyhow!(
+                "Pass {} depends on its own current output, sample its previous output with layout(pass = {0}, previous)",
+                names[i]
+            ));
+        }
+        visiting.push(i);
+        for buffer in inputs[i].iter().filter(|it| !it.previous) {
+            let dep = names.iter().position(|it| *it == buffer.pass).unwrap();
+            visit(dep, names, inputs, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(i);
+        Ok(())
+    }
+
+    let mut order = Vec::with_capacity(names.len());
+    for i in (1..names.len()).chain(Some(0)) {
+        visit(i, names, inputs, &mut Vec::new(), &mut order)?;
+    }
+    if order.last() != Some(&0) {
+        return Err(anyhow!(
+            "The current output of the displayed pass {} can't be sampled, sample its previous output",
+            names[0]
+        ));
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(pass: &str, previous: bool) -> BufferInput {
+        BufferInput {
+            pass: pass.to_string(),
+            previous,
+        }
+    }
+
+    #[test]
+    fn passes_after_their_inputs() {
+        // main <- blur <- sim, sim samples its own previous output
+        let names = ["main", "sim", "blur"];
+        let main = [input("blur", false)];
+        let sim = [input("sim", true)];
+        let blur = [input("sim", false), input("main", true)];
+        let order = order_passes(&names, &[&main, &sim, &blur]).unwrap();
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let names = ["main", "a", "b"];
+        let a = [input("b", false)];
+        let b = [input("a", false)];
+        assert!(order_passes(&names, &[&[], &a, &b]).is_err());
+        // Sampling the previous output breaks the cycle
+        let b = [input("a", true)];
+        assert_eq!(order_passes(&names, &[&[], &a, &b]).unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn current_output_of_itself_is_rejected() {
+        let names = ["main", "a"];
+        let a = [input("a", false)];
+        assert!(order_passes(&names, &[&[], &a]).is_err());
+    }
+
+    #[test]
+    fn displayed_pass_is_last() {
+        let names = ["main", "a"];
+        let a = [input("main", false)];
+        assert!(order_passes(&names, &[&[], &a]).is_err());
+    }
+}
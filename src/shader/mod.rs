@@ -1,12 +1,25 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use anyhow::{anyhow, Result};
 use crevice::std140;
 use crevice::std430::AsStd430;
-use mint::{Vector2, Vector3};
+use mint::{Vector2, Vector3, Vector4};
+use serde::Deserialize;
+use shaderc::ShaderKind;
 
+pub mod audio;
+pub mod hlsl;
 pub mod loader;
+pub mod offline;
 pub mod preprocessor;
+pub mod project;
+pub mod reflection;
 pub mod renderer;
+pub mod shadertoy;
+pub mod sound;
+pub mod spirv;
+pub mod wgsl;
 
 /// The globals we pass to the fragment shader
 #[derive(AsStd430, Clone)]
@@ -23,16 +36,76 @@ pub struct Globals {
     pub time: f32,
     /// Number of frame
     pub frame: u32,
+    /// Time elapsed since the last frame in sec
+    pub time_delta: f32,
+    /// Current date (UTC) : year, month (0-11), day (1-31), seconds since midnight
+    pub date: Vector4<f32>,
+    /// Levels of the bass, mid and treble bands of the first audio channel
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
 }
 
 impl Globals {
     pub fn reset(&mut self) {
         self.frame = 0;
         self.time = 0.0;
+        self.time_delta = 0.0;
         self.mouse_wheel = 0.0;
     }
+
+    /// Set the audio levels globals, they are 0 without audio channel
+    pub fn set_audio_levels(&mut self, levels: Option<[f32; 3]>) {
+        let [bass, mid, treble] = levels.unwrap_or_default();
+        self.bass = bass;
+        self.mid = mid;
+        self.treble = treble;
+    }
+
+    /// Set the date global from a system time
+    pub fn set_date(&mut self, now: SystemTime) {
+        let (year, month, day, seconds) = civil_date(now);
+        self.date = Vector4::from([year as f32, (month - 1) as f32, day as f32, seconds as f32]);
+    }
+}
+
+/// Year, month (1-12), day (1-31) and seconds since midnight (UTC) of a system time
+pub fn civil_date(date: SystemTime) -> (i64, u32, u32, f64) {
+    let since_epoch = date
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let days = (since_epoch / 86400.0).floor() as i64;
+    let seconds = since_epoch - days as f64 * 86400.0;
+
+    // Convert days since epoch to a civil date
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month as u32, day as u32, seconds)
 }
 
+/// Midnight (UTC) of a civil date, the month is 1-12. Dates before 1970 are clamped to it.
+pub fn date_from_civil(year: i64, month: u32, day: u32) -> SystemTime {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let (month, day) = (month.clamp(1, 12) as i64, day.clamp(1, 31) as i64);
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    UNIX_EPOCH + Duration::from_secs(days.max(0) as u64 * 86400)
+}
+
+#[derive(Clone)]
 pub enum Slider {
     Float {
         name: String,
@@ -101,6 +174,79 @@ macro_rules! reset_impl {
 
 reset_impl!(Slider, Float Uint Bool Vec2 Vec3 Color);
 
+impl Slider {
+    pub fn name(&self) -> &str {
+        match self {
+            Slider::Float { name, .. }
+            | Slider::Uint { name, .. }
+            | Slider::Bool { name, .. }
+            | Slider::Vec2 { name, .. }
+            | Slider::Vec3 { name, .. }
+            | Slider::Color { name, .. } => name,
+        }
+    }
+
+    fn check_components(&self, values: &[f32]) -> Result<()> {
+        let components = match self {
+            Slider::Vec2 { .. } => 2,
+            Slider::Vec3 { .. } | Slider::Color { .. } => 3,
+            _ => 1,
+        };
+        if values.len() != components {
+            return Err(anyhow!(
+                "Param {} expects {} values, got {}",
+                self.name(),
+                components,
+                values.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Components of the value as floats
+    pub fn value(&self) -> Vec<f32> {
+        match self {
+            Slider::Float { value, .. } => vec![*value],
+            Slider::Uint { value, .. } | Slider::Bool { value, .. } => vec![*value as f32],
+            Slider::Vec2 { value, .. } => vec![value.x, value.y],
+            Slider::Vec3 { value, .. } | Slider::Color { value, .. } => {
+                vec![value.x, value.y, value.z]
+            }
+        }
+    }
+
+    /// Set the value from its components, integers and booleans are rounded
+    pub fn set_value(&mut self, values: &[f32]) -> Result<()> {
+        self.check_components(values)?;
+        match self {
+            Slider::Float { value, .. } => *value = values[0],
+            Slider::Uint { value, .. } => *value = values[0].round().max(0.0) as u32,
+            Slider::Bool { value, .. } => *value = (values[0].round() != 0.0) as u32,
+            Slider::Vec2 { value, .. } => *value = Vector2::from([values[0], values[1]]),
+            Slider::Vec3 { value, .. } | Slider::Color { value, .. } => {
+                *value = Vector3::from([values[0], values[1], values[2]])
+            }
+        }
+        Ok(())
+    }
+
+    /// Override the default value, the value is reset to it
+    pub fn set_default(&mut self, values: &[f32]) -> Result<()> {
+        self.check_components(values)?;
+        match self {
+            Slider::Float { default, .. } => *default = values[0],
+            Slider::Uint { default, .. } => *default = values[0] as u32,
+            Slider::Bool { default, .. } => *default = (values[0] != 0.0) as u32,
+            Slider::Vec2 { default, .. } => *default = Vector2::from([values[0], values[1]]),
+            Slider::Vec3 { default, .. } | Slider::Color { default, .. } => {
+                *default = Vector3::from([values[0], values[1], values[2]])
+            }
+        }
+        self.reset();
+        Ok(())
+    }
+}
+
 macro_rules! write_impl {
     ($align:ident, $enum:ident, $($item:ident )*) => {
         impl $enum {
@@ -117,11 +263,103 @@ macro_rules! write_impl {
 
 write_impl!(std140, Slider, Float Uint Bool Vec2 Vec3 Color);
 
+/// The output of a pass sampled by a shader, declared with `layout(pass = name)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferInput {
+    /// Name of the pass
+    pub pass: String,
+    /// Sample the output of the last frame
+    pub previous: bool,
+}
+
+/// Type of the voxels of a raw volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Voxel {
+    U8,
+    U16,
+    F32,
+}
+
+/// Image files sampled by a shader
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TextureFile {
+    /// A 2D image, bound as a texture2D
+    Image(PathBuf),
+    /// A directory with the faces of a cube (px, nx, py, ny, pz and nz images) or an
+    /// equirectangular image, bound as a textureCube
+    Cube(PathBuf),
+    /// A raw volume of little endian voxels (x first, then y and z), bound as a texture3D
+    Volume {
+        path: PathBuf,
+        size: [u32; 3],
+        voxel: Voxel,
+    },
+    /// A directory of images sorted by name, each one is a slice of a volume bound as a texture3D
+    Slices(PathBuf),
+}
+
+impl TextureFile {
+    pub fn path(&self) -> &Path {
+        match self {
+            TextureFile::Image(path)
+            | TextureFile::Cube(path)
+            | TextureFile::Volume { path, .. }
+            | TextureFile::Slices(path) => path,
+        }
+    }
+
+    /// The same texture with its path relative to a directory
+    pub fn joined(&self, dir: &Path) -> Self {
+        let mut joined = self.clone();
+        match &mut joined {
+            TextureFile::Image(path)
+            | TextureFile::Cube(path)
+            | TextureFile::Volume { path, .. }
+            | TextureFile::Slices(path) => *path = dir.join(&path),
+        }
+        joined
+    }
+
+    /// Type of the texture in glsl
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            TextureFile::Image(_) => "texture2D",
+            TextureFile::Cube(_) => "textureCube",
+            TextureFile::Volume { .. } | TextureFile::Slices(_) => "texture3D",
+        }
+    }
+}
+
+/// A texture declared in a shader with `layout(texture = "path")`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureInput {
+    /// Name of the texture in the shader
+    pub name: String,
+    /// The files to load, relative to the shader
+    pub file: TextureFile,
+}
+
 /// Traverses the ast and extract useful data while converting the ast to valid glsl source
 #[derive(Default)]
 pub struct ShaderMetadata {
     pub sliders: Vec<Slider>,
     pub still_image: bool,
+    /// The shader is written for Shadertoy, its output is upside down
+    pub shadertoy: bool,
+    /// The shader declares `mainSound`, it is rendered to samples instead of an image
+    pub sound: bool,
+    /// Pass outputs sampled by the shader, by channel
+    pub buffers: Vec<BufferInput>,
+    /// Textures declared in the shader, by binding
+    pub textures: Vec<TextureInput>,
+    /// How the previous frame is sampled
+    pub previous: PreviousSampler,
+    /// Format of the displayed pass output requested by the shader
+    pub target_format: Option<TargetFormat>,
+    /// Storage buffer the displayed pass writes values to
+    pub output: Option<OutputBlock>,
+    /// How the primitives of a vertex shader are drawn
+    pub vertex: VertexSettings,
 }
 
 impl ShaderMetadata {
@@ -130,14 +368,7 @@ impl ShaderMetadata {
     }
 
     pub fn params_buffer(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        let mut writer = std140::Writer::new(&mut bytes);
-
-        for slider in self.sliders.iter() {
-            slider.write(&mut writer);
-        }
-
-        bytes
+        params_buffer(&self.sliders)
     }
 
     pub fn reset_params(&mut self) {
@@ -147,6 +378,218 @@ impl ShaderMetadata {
     }
 }
 
+/// The std140 params uniform block of some sliders
+pub fn params_buffer(sliders: &[Slider]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut writer = std140::Writer::new(&mut bytes);
+
+    for slider in sliders.iter() {
+        slider.write(&mut writer);
+    }
+
+    // The size of a uniform block is rounded up to a multiple of 16
+    bytes.resize(bytes.len().next_multiple_of(16), 0);
+    bytes
+}
+
+/// Type of the components of an output field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputScalar {
+    Float,
+    Int,
+    Uint,
+}
+
+/// A field of an output block, a scalar, a vector or an array of those
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputField {
+    pub name: String,
+    pub scalar: OutputScalar,
+    /// Number of components, 1 for scalars
+    pub components: u32,
+    /// Number of elements of an array field
+    pub length: Option<u32>,
+    /// Offset of the field in the block, with the std430 layout
+    pub offset: u32,
+    /// Distance between the elements of an array field
+    pub stride: u32,
+}
+
+impl OutputField {
+    /// Number of values of the field, the components of each element
+    pub fn value_count(&self) -> usize {
+        (self.length.unwrap_or(1) * self.components) as usize
+    }
+}
+
+/// A storage buffer declared with `layout(output) buffer Name { ... }`, read back by Nuance
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputBlock {
+    /// Name of the block
+    pub name: String,
+    pub fields: Vec<OutputField>,
+    /// Size of the buffer in bytes
+    pub size: u64,
+}
+
+impl OutputBlock {
+    /// Decode the values of each field from the content of the buffer
+    pub fn read(&self, bytes: &[u8]) -> Vec<Vec<f64>> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let mut values = Vec::with_capacity(field.value_count());
+                for element in 0..field.length.unwrap_or(1) {
+                    for component in 0..field.components {
+                        let at = (field.offset + element * field.stride + 4 * component) as usize;
+                        let word = bytes
+                            .get(at..at + 4)
+                            .map(|it| [it[0], it[1], it[2], it[3]])
+                            .unwrap_or_default();
+                        values.push(match field.scalar {
+                            OutputScalar::Float => f32::from_ne_bytes(word) as f64,
+                            OutputScalar::Int => i32::from_ne_bytes(word) as f64,
+                            OutputScalar::Uint => u32::from_ne_bytes(word) as f64,
+                        });
+                    }
+                }
+                values
+            })
+            .collect()
+    }
+}
+
+/// What a channel of a pass is bound to
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Channel {
+    /// An empty black texture
+    #[default]
+    Empty,
+    /// The output of a pass. The current output can only be sampled from a pass rendered after
+    /// it, `previous` selects the output of the last frame.
+    Pass { index: usize, previous: bool },
+    /// An image file
+    Texture(PathBuf),
+    /// The spectrum and waveform of a WAV or FLAC file at the current time
+    Audio(PathBuf),
+}
+
+/// Texture filtering of a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+/// What happens when a channel is sampled outside of the 0-1 range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// How a channel is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChannelSampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    /// Sample the mipmaps of image textures
+    pub mipmaps: bool,
+}
+
+/// Format of the texture the displayed pass renders to.
+/// Float targets keep the precision of simulations and are converted for display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TargetFormat {
+    /// The format of the window surface, 8 bits sRGB
+    #[default]
+    Surface,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+/// How the colors written by the displayed pass are shown and exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Colors are linear and encoded to sRGB
+    #[default]
+    Srgb,
+    /// Colors are shown and exported as written
+    Passthrough,
+}
+
+/// How the vertices of a vertex shader are assembled into primitives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Topology {
+    #[default]
+    Points,
+    Lines,
+    LineStrip,
+    Triangles,
+    TriangleStrip,
+}
+
+/// How the primitives of a vertex shader are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexSettings {
+    /// Number of vertices drawn each frame
+    pub count: u32,
+    pub topology: Topology,
+    /// Smooth the edges of the primitives with 4x multisampling
+    pub msaa: bool,
+    /// Add the colors of overlapping primitives instead of drawing over
+    pub additive: bool,
+}
+
+impl Default for VertexSettings {
+    fn default() -> Self {
+        Self {
+            count: 10000,
+            topology: Topology::Points,
+            msaa: false,
+            additive: false,
+        }
+    }
+}
+
+/// How the previous output of a pass is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PreviousSampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl Default for PreviousSampler {
+    fn default() -> Self {
+        Self {
+            filter: Filter::Nearest,
+            wrap: Wrap::Repeat,
+        }
+    }
+}
+
+/// A render pass of a shader. Passes are rendered in order, the last one is displayed.
+#[derive(Clone)]
+pub struct Pass {
+    /// Name of the pass, as displayed
+    pub name: String,
+    /// The fragment entry point of this pass
+    pub entry_point: String,
+    /// Inputs bound to the channels of this pass
+    pub channels: [Channel; renderer::CHANNEL_COUNT],
+    /// How each channel is sampled
+    pub samplers: [ChannelSampler; renderer::CHANNEL_COUNT],
+    /// How the previous output of this pass is sampled
+    pub previous: PreviousSampler,
+    /// Textures declared in the shader, bound at set 3
+    pub textures: Vec<TextureFile>,
+}
+
 pub struct Shader {
     /// Path to the main shader file
     pub main: PathBuf,
@@ -154,4 +597,60 @@ pub struct Shader {
     pub sources: Vec<PathBuf>,
     /// Shader metadata extracted before compilation
     pub metadata: Option<ShaderMetadata>,
+    /// Fragment shaders render their passes, compute shaders write to the displayed image and
+    /// vertex shaders draw primitives on it
+    pub kind: ShaderKind,
+    /// Names of the fragment entry points available in the displayed pass
+    pub entry_points: Vec<String>,
+    /// Render passes, there is at least one
+    pub passes: Vec<Pass>,
+    /// Fixed canvas resolution, follows the window size if not set
+    pub resolution: Option<Vector2<u32>>,
+    /// Target framerate
+    pub framerate: Option<f32>,
+}
+
+impl Shader {
+    fn new(
+        path: &Path,
+        metadata: Option<ShaderMetadata>,
+        entry_points: Vec<String>,
+    ) -> Result<Self> {
+        let pass = Pass {
+            name: path
+                .file_stem()
+                .map(|it| it.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            entry_point: default_entry_point(&entry_points)?,
+            channels: Default::default(),
+            samplers: Default::default(),
+            previous: metadata.as_ref().map(|it| it.previous).unwrap_or_default(),
+            textures: Vec::new(),
+        };
+        Ok(Self {
+            main: path.to_path_buf(),
+            sources: vec![path.to_path_buf()],
+            metadata,
+            kind: ShaderKind::Fragment,
+            entry_points,
+            passes: vec![pass],
+            resolution: None,
+            framerate: None,
+        })
+    }
+
+    /// The entry point currently in use in the displayed pass
+    pub fn entry_point(&self) -> &str {
+        &self.passes.last().unwrap().entry_point
+    }
+}
+
+/// Prefer the conventional main if there is one
+fn default_entry_point(entry_points: &[String]) -> Result<String> {
+    entry_points
+        .iter()
+        .find(|it| it.as_str() == "main")
+        .or_else(|| entry_points.first())
+        .cloned()
+        .ok_or_else(|| anyhow!("No fragment entry point in the shader"))
 }
@@ -0,0 +1,270 @@
+//! Deterministic timing of offline renders.
+//!
+//! Offline frames don't follow the wall clock : the globals and the params of a frame only depend
+//! on its number, so a sequence renders the same on every run whatever the speed of the GPU.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+
+use crate::{params_buffer, Globals, Slider};
+
+/// Values of a param over time, linearly interpolated between keyframes and held before the first
+/// and after the last one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframes {
+    /// Name of the param
+    pub param: String,
+    /// Time in sec and components of the value, sorted by time
+    keys: Vec<(f64, Vec<f32>)>,
+}
+
+impl Keyframes {
+    pub fn new(param: &str) -> Self {
+        Self {
+            param: param.to_string(),
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[(f64, Vec<f32>)] {
+        &self.keys
+    }
+
+    /// Add a keyframe, it replaces the keyframe at the same time
+    pub fn insert(&mut self, time: f64, value: Vec<f32>) {
+        match self.keys.binary_search_by(|(it, _)| it.total_cmp(&time)) {
+            Ok(i) => self.keys[i].1 = value,
+            Err(i) => self.keys.insert(i, (time, value)),
+        }
+    }
+
+    /// Remove the keyframe at the given time
+    pub fn remove(&mut self, time: f64) {
+        self.keys.retain(|(it, _)| *it != time);
+    }
+
+    /// The value at the given time, None without keyframes
+    pub fn sample(&self, time: f64) -> Option<Vec<f32>> {
+        let next = self.keys.partition_point(|(it, _)| *it <= time);
+        match (
+            next.checked_sub(1).map(|i| &self.keys[i]),
+            self.keys.get(next),
+        ) {
+            (Some((t0, v0)), Some((t1, v1))) => {
+                let t = ((time - t0) / (t1 - t0)) as f32;
+                Some(v0.iter().zip(v1).map(|(a, b)| a + (b - a) * t).collect())
+            }
+            (Some((_, value)), None) | (None, Some((_, value))) => Some(value.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Time of each frame of an offline render
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameClock {
+    /// Time of the first frame in sec
+    pub start: f64,
+    /// Time between two frames in sec
+    pub timestep: f64,
+    pub frame_count: u32,
+    /// Date at time 0, the date of a frame is its time after it
+    pub date: SystemTime,
+    /// Values of the params over time, params without keyframes keep their value
+    pub keyframes: Vec<Keyframes>,
+}
+
+impl FrameClock {
+    pub fn new(start: f64, timestep: f64, frame_count: u32, date: SystemTime) -> Self {
+        Self {
+            start,
+            timestep,
+            frame_count,
+            date,
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// Time of a frame in sec, computed from its number so errors don't accumulate
+    pub fn time(&self, frame: u32) -> f64 {
+        self.start + frame as f64 * self.timestep
+    }
+
+    /// The globals of a frame. The frame number counts from 0. The resolution, the mouse and the
+    /// audio levels are kept from `initial`.
+    pub fn globals(&self, frame: u32, initial: &Globals) -> Globals {
+        let time = self.time(frame);
+        let mut globals = initial.clone();
+        globals.frame = frame;
+        globals.time = time as f32;
+        globals.time_delta = self.timestep as f32;
+        globals.set_date(if time >= 0.0 {
+            self.date + Duration::from_secs_f64(time)
+        } else {
+            self.date - Duration::from_secs_f64(-time)
+        });
+        globals
+    }
+
+    /// Set the keyframed params to their value at the given time
+    pub fn apply_keyframes(&self, time: f64, sliders: &mut [Slider]) -> Result<()> {
+        for keyframes in self.keyframes.iter() {
+            let slider = sliders
+                .iter_mut()
+                .find(|it| it.name() == keyframes.param)
+                .ok_or_else(|| anyhow!("There is no param named {} to animate", keyframes.param))?;
+            if let Some(value) = keyframes.sample(time) {
+                slider.set_value(&value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drive a render loop over all the frames in order.
+    ///
+    /// The keyframed params are interpolated before each frame. `inputs` then sets the other inputs
+    /// of a frame, it must only depend on the frame and its globals for the sequence to be
+    /// reproducible. `render` renders the frame with its globals and params buffer.
+    pub fn run(
+        &self,
+        initial: &Globals,
+        sliders: &[Slider],
+        mut inputs: impl FnMut(u32, &mut Globals),
+        mut render: impl FnMut(u32, &Globals, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let mut sliders = sliders.to_vec();
+        for frame in 0..self.frame_count {
+            let mut globals = self.globals(frame, initial);
+            self.apply_keyframes(self.time(frame), &mut sliders)?;
+            inputs(frame, &mut globals);
+            render(frame, &globals, &params_buffer(&sliders))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use mint::{Vector2, Vector4};
+
+    use super::*;
+    use crate::date_from_civil;
+
+    fn globals() -> Globals {
+        Globals {
+            resolution: Vector2::from([640, 360]),
+            mouse: Vector2::from([10, 20]),
+            mouse_wheel: 0.0,
+            ratio: 640.0 / 360.0,
+            time: 0.0,
+            frame: 0,
+            time_delta: 0.0,
+            date: Vector4::from([0.0; 4]),
+            bass: 0.0,
+            mid: 0.0,
+            treble: 0.0,
+        }
+    }
+
+    fn slider(value: f32) -> Slider {
+        Slider::Float {
+            name: "speed".to_string(),
+            min: 0.0,
+            max: 100.0,
+            value,
+            default: value,
+        }
+    }
+
+    #[test]
+    fn frame_times_dont_accumulate_errors() {
+        let clock = FrameClock::new(1.0, 1.0 / 60.0, 6001, UNIX_EPOCH);
+        assert_eq!(clock.time(0), 1.0);
+        assert_eq!(clock.time(60), 2.0);
+        assert_eq!(clock.time(6000), 101.0);
+    }
+
+    #[test]
+    fn globals_of_a_frame() {
+        let date = date_from_civil(2024, 12, 31);
+        let clock = FrameClock::new(3600.0 - 0.5, 0.25, 4, date);
+        let globals = clock.globals(2, &globals());
+        assert_eq!(globals.frame, 2);
+        assert_eq!(globals.time, 3600.0);
+        assert_eq!(globals.time_delta, 0.25);
+        assert_eq!(globals.resolution, Vector2::from([640, 360]));
+        assert_eq!(globals.mouse, Vector2::from([10, 20]));
+        // An hour after midnight, months count from 0
+        assert_eq!(globals.date, Vector4::from([2024.0, 11.0, 31.0, 3600.0]));
+    }
+
+    #[test]
+    fn negative_times_are_before_the_date() {
+        let clock = FrameClock::new(-60.0, 1.0, 1, date_from_civil(2000, 3, 1));
+        let globals = clock.globals(0, &globals());
+        assert_eq!(globals.date, Vector4::from([2000.0, 1.0, 29.0, 86340.0]));
+    }
+
+    #[test]
+    fn keyframes_are_interpolated_and_held() {
+        let mut keyframes = Keyframes::new("speed");
+        keyframes.insert(2.0, vec![20.0]);
+        keyframes.insert(0.0, vec![0.0]);
+        keyframes.insert(1.0, vec![5.0]);
+        keyframes.insert(1.0, vec![10.0]);
+        assert_eq!(keyframes.keys().len(), 3);
+        assert_eq!(keyframes.sample(-1.0), Some(vec![0.0]));
+        assert_eq!(keyframes.sample(0.5), Some(vec![5.0]));
+        assert_eq!(keyframes.sample(1.5), Some(vec![15.0]));
+        assert_eq!(keyframes.sample(3.0), Some(vec![20.0]));
+        keyframes.remove(2.0);
+        assert_eq!(keyframes.sample(3.0), Some(vec![10.0]));
+        assert_eq!(Keyframes::new("speed").sample(0.0), None);
+    }
+
+    #[test]
+    fn run_renders_every_frame_in_order() {
+        let mut clock = FrameClock::new(0.0, 0.5, 5, UNIX_EPOCH);
+        let mut keyframes = Keyframes::new("speed");
+        keyframes.insert(0.0, vec![0.0]);
+        keyframes.insert(2.0, vec![40.0]);
+        clock.keyframes.push(keyframes);
+
+        let mut rendered = Vec::new();
+        clock
+            .run(
+                &globals(),
+                &[slider(1.0)],
+                |frame, globals| globals.bass = frame as f32,
+                |frame, globals, params| {
+                    assert_eq!(globals.bass, frame as f32);
+                    let speed = f32::from_ne_bytes(params[..4].try_into().unwrap());
+                    rendered.push((frame, globals.time, speed));
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            rendered,
+            vec![
+                (0, 0.0, 0.0),
+                (1, 0.5, 10.0),
+                (2, 1.0, 20.0),
+                (3, 1.5, 30.0),
+                (4, 2.0, 40.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_keyframed_params_are_rejected() {
+        let mut clock = FrameClock::new(0.0, 1.0, 1, UNIX_EPOCH);
+        clock.keyframes.push(Keyframes::new("other"));
+        assert!(clock
+            .run(&globals(), &[slider(1.0)], |_, _| {}, |_, _, _| Ok(()))
+            .is_err());
+    }
+}
@@ -2,11 +2,14 @@
 
 use core::panic;
 use std::borrow::Borrow;
+use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
 use glsl_lang::ast::{
-    BlockData, ExprData, FunIdentifierData, IdentifierData, LayoutQualifierData,
-    LayoutQualifierSpecData, Node, PreprocessorDefineData, SmolStr, StructFieldSpecifierData,
+    ArraySpecifierDimensionData, BlockData, DeclarationData, ExprData, ExternalDeclaration,
+    ExternalDeclarationData, FunIdentifierData, FunctionParameterDeclarationData,
+    FunctionPrototypeData, IdentifierData, LayoutQualifierData, LayoutQualifierSpecData, Node,
+    PreprocessorDefineData, SmolStr, StorageQualifierData, StructFieldSpecifierData,
     TranslationUnit, TypeQualifierData, TypeQualifierSpecData, TypeSpecifierData,
     TypeSpecifierNonArrayData,
 };
@@ -14,10 +17,20 @@ use glsl_lang::parse::{DefaultLexer, Parse, ParseBuilder, ParseContext, ParseOpt
 use glsl_lang::transpiler::glsl::{show_translation_unit, FormattingState};
 use glsl_lang::visitor::{HostMut, Visit, VisitorMut};
 use lang_util::FileId;
-use log::{debug, error};
+use log::{debug, error, warn};
 use mint::{Vector2, Vector3};
 
-use crate::{ShaderMetadata, Slider};
+use crate::renderer::CHANNEL_COUNT;
+use crate::shader::{shadertoy, sound};
+use crate::{
+    BufferInput, Filter, OutputBlock, OutputField, OutputScalar, ShaderMetadata, Slider,
+    TargetFormat, TextureFile, TextureInput, Topology, Voxel, Wrap,
+};
+
+/// Texture declarations are replaced by this pragma while the source is parsed
+const TEXTURE_PRAGMA: &str = "nuance_texture";
+/// `output` is a reserved word, the output block qualifier is renamed while the source is parsed
+const OUTPUT_QUALIFIER: &str = "nuance_output";
 
 impl VisitorMut for ShaderMetadata {
     fn visit_block(&mut self, block: &mut Node<BlockData>) -> Visit {
@@ -52,14 +65,63 @@ impl VisitorMut for ShaderMetadata {
     }
 
     fn visit_preprocessor_define(&mut self, define: &mut Node<PreprocessorDefineData>) -> Visit {
-        if let PreprocessorDefineData::ObjectLike { ident, .. } = &define.content {
-            if ident.content.0.as_str() == "NUANCE_STILL_IMAGE" {
-                self.still_image = true;
+        if let PreprocessorDefineData::ObjectLike { ident, value } = &define.content {
+            match (ident.content.0.as_str(), value.trim()) {
+                ("NUANCE_STILL_IMAGE", _) => {
+                    self.still_image = true;
+                }
+                ("NUANCE_PREVIOUS_FILTER", value) => match value {
+                    "nearest" => self.previous.filter = Filter::Nearest,
+                    "linear" => self.previous.filter = Filter::Linear,
+                    other => warn!("Unknown previous frame filter {}", other),
+                },
+                ("NUANCE_PREVIOUS_WRAP", value) => match value {
+                    "clamp" => self.previous.wrap = Wrap::Clamp,
+                    "repeat" => self.previous.wrap = Wrap::Repeat,
+                    "mirror" => self.previous.wrap = Wrap::Mirror,
+                    other => warn!("Unknown previous frame wrap mode {}", other),
+                },
+                ("NUANCE_TARGET_FORMAT", value) => match value {
+                    "rgba16f" => self.target_format = Some(TargetFormat::Rgba16Float),
+                    "rgba32f" => self.target_format = Some(TargetFormat::Rgba32Float),
+                    other => warn!("Unknown target format {}", other),
+                },
+                ("NUANCE_VERTEX_COUNT", value) => match value.parse() {
+                    Ok(count) => self.vertex.count = count,
+                    Err(_) => warn!("Invalid vertex count {}", value),
+                },
+                ("NUANCE_TOPOLOGY", value) => match value {
+                    "points" => self.vertex.topology = Topology::Points,
+                    "lines" => self.vertex.topology = Topology::Lines,
+                    "line_strip" => self.vertex.topology = Topology::LineStrip,
+                    "triangles" => self.vertex.topology = Topology::Triangles,
+                    "triangle_strip" => self.vertex.topology = Topology::TriangleStrip,
+                    other => warn!("Unknown topology {}", other),
+                },
+                ("NUANCE_MSAA", _) => {
+                    self.vertex.msaa = true;
+                }
+                ("NUANCE_BLEND", value) => match value {
+                    "additive" => self.vertex.additive = true,
+                    "none" => self.vertex.additive = false,
+                    other => warn!("Unknown blend mode {}", other),
+                },
+                _ => {}
             }
         }
         Visit::Parent
     }
 
+    fn visit_function_prototype(&mut self, proto: &mut Node<FunctionPrototypeData>) -> Visit {
+        if proto.content.name.content.0 == shadertoy::MAIN_IMAGE && is_main_image(&proto.content) {
+            self.shadertoy = true;
+        }
+        if proto.content.name.content.0 == sound::MAIN_SOUND && is_main_sound(&proto.content) {
+            self.sound = true;
+        }
+        Visit::Parent
+    }
+
     fn visit_expr(&mut self, expr: &mut Node<ExprData>) -> Visit {
         if let ExprData::Dot(expr2, ident1) = &mut expr.content {
             if let ExprData::Variable(ident0) = &expr2.as_ref().content {
@@ -146,6 +208,77 @@ impl VisitorMut for ShaderMetadata {
     }
 }
 
+/// Type and storage qualifier of a parameter of a function
+fn parameter(
+    proto: &FunctionPrototypeData,
+    i: usize,
+) -> Option<(Option<&StorageQualifierData>, &TypeSpecifierNonArrayData)> {
+    proto
+        .parameters
+        .get(i)
+        .map(|param| match &param.content {
+            FunctionParameterDeclarationData::Named(qualifier, declarator) => {
+                (qualifier, &declarator.content.ty.content.ty.content)
+            }
+            FunctionParameterDeclarationData::Unnamed(qualifier, ty) => {
+                (qualifier, &ty.content.ty.content)
+            }
+        })
+        .map(|(qualifier, ty)| {
+            let storage = qualifier.as_ref().and_then(|it| {
+                it.content
+                    .qualifiers
+                    .iter()
+                    .find_map(|it| match &it.content {
+                        TypeQualifierSpecData::Storage(storage) => Some(&storage.content),
+                        _ => None,
+                    })
+            });
+            (storage, ty)
+        })
+}
+
+/// Check the signature is `mainImage(out vec4, in vec2)`
+fn is_main_image(proto: &FunctionPrototypeData) -> bool {
+    proto.parameters.len() == 2
+        && matches!(
+            parameter(proto, 0),
+            Some((
+                Some(StorageQualifierData::Out),
+                TypeSpecifierNonArrayData::Vec4
+            ))
+        )
+        && matches!(
+            parameter(proto, 1),
+            Some((
+                None | Some(StorageQualifierData::In),
+                TypeSpecifierNonArrayData::Vec2
+            ))
+        )
+}
+
+/// Check the signature is `vec2 mainSound(in int, in float)`
+fn is_main_sound(proto: &FunctionPrototypeData) -> bool {
+    matches!(
+        proto.ty.content.ty.content.ty.content,
+        TypeSpecifierNonArrayData::Vec2
+    ) && proto.parameters.len() == 2
+        && matches!(
+            parameter(proto, 0),
+            Some((
+                None | Some(StorageQualifierData::In),
+                TypeSpecifierNonArrayData::Int
+            ))
+        )
+        && matches!(
+            parameter(proto, 1),
+            Some((
+                None | Some(StorageQualifierData::In),
+                TypeSpecifierNonArrayData::Float
+            ))
+        )
+}
+
 fn process_layout_qualifier_on_field(
     field: &StructFieldSpecifierData,
     mut consumer: impl FnMut(&str, &Node<ExprData>),
@@ -361,23 +494,28 @@ pub fn create_slider_from_field(field: &StructFieldSpecifierData) -> Result<Slid
 
 /// Replace the layout(params) with a predefined layout(set=?, binding=?)
 pub fn convert_params_block(block: &mut BlockData) {
+    convert_block_layout(block, "std140", 1, 0);
+}
+
+/// Replace the first layout qualifier of a block with layout(packing, set=?, binding=?)
+fn convert_block_layout(block: &mut BlockData, packing: &str, set: i32, binding: i32) {
     // I could have used glsl-lang-quote instead of creating the ast by hand
     block.qualifier.content.qualifiers[0] = TypeQualifierSpecData::Layout(
         LayoutQualifierData {
             ids: vec![
                 LayoutQualifierSpecData::Identifier(
-                    IdentifierData(SmolStr::new("std140")).into(),
+                    IdentifierData(SmolStr::new(packing)).into(),
                     None,
                 )
                 .into(),
                 LayoutQualifierSpecData::Identifier(
                     IdentifierData(SmolStr::new("set")).into(),
-                    Some(Box::new(ExprData::IntConst(1).into())),
+                    Some(Box::new(ExprData::IntConst(set).into())),
                 )
                 .into(),
                 LayoutQualifierSpecData::Identifier(
                     IdentifierData(SmolStr::new("binding")).into(),
-                    Some(Box::new(ExprData::IntConst(0).into())),
+                    Some(Box::new(ExprData::IntConst(binding).into())),
                 )
                 .into(),
             ],
@@ -387,10 +525,197 @@ pub fn convert_params_block(block: &mut BlockData) {
     .into();
 }
 
-pub fn extract(source: &str) -> Result<(ShaderMetadata, String)> {
-    let mut metadata = ShaderMetadata::default();
+/// Find the `layout(output) buffer` block, its layout is computed and it is bound next to the
+/// params (set 1, binding 1)
+fn extract_output(ast: &mut TranslationUnit, metadata: &mut ShaderMetadata) -> Result<()> {
+    for decl in ast.0.iter_mut() {
+        let block = match &mut decl.content {
+            ExternalDeclarationData::Declaration(decl) => match &mut decl.content {
+                DeclarationData::Block(block) => &mut block.content,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let is_output = block.qualifier.qualifiers.iter().any(|it| {
+            matches!(&it.content, TypeQualifierSpecData::Layout(layout)
+                if layout.ids.iter().any(|id| matches!(&id.content,
+                    LayoutQualifierSpecData::Identifier(key, None) if key.0 == OUTPUT_QUALIFIER)))
+        });
+        if !is_output {
+            continue;
+        }
+        if metadata.output.is_some() {
+            return Err(anyhow!("Only one output block can be declared"));
+        }
+        let is_buffer = block.qualifier.qualifiers.iter().any(|it| {
+            matches!(&it.content, TypeQualifierSpecData::Storage(storage)
+                if storage.content == StorageQualifierData::Buffer)
+        });
+        if !is_buffer {
+            return Err(anyhow!(
+                "The output block {} must be a buffer block",
+                block.name.0
+            ));
+        }
+
+        let mut fields = Vec::new();
+        let mut offset = 0u32;
+        let mut block_align = 4;
+        for field in block.fields.iter() {
+            let (scalar, components) = match &field.ty.ty.content {
+                TypeSpecifierNonArrayData::Float => (OutputScalar::Float, 1),
+                TypeSpecifierNonArrayData::Vec2 => (OutputScalar::Float, 2),
+                TypeSpecifierNonArrayData::Vec3 => (OutputScalar::Float, 3),
+                TypeSpecifierNonArrayData::Vec4 => (OutputScalar::Float, 4),
+                TypeSpecifierNonArrayData::Int => (OutputScalar::Int, 1),
+                TypeSpecifierNonArrayData::IVec2 => (OutputScalar::Int, 2),
+                TypeSpecifierNonArrayData::IVec3 => (OutputScalar::Int, 3),
+                TypeSpecifierNonArrayData::IVec4 => (OutputScalar::Int, 4),
+                TypeSpecifierNonArrayData::UInt => (OutputScalar::Uint, 1),
+                TypeSpecifierNonArrayData::UVec2 => (OutputScalar::Uint, 2),
+                TypeSpecifierNonArrayData::UVec3 => (OutputScalar::Uint, 3),
+                TypeSpecifierNonArrayData::UVec4 => (OutputScalar::Uint, 4),
+                other => return Err(anyhow!("Unsupported type in output block : {:?}", other)),
+            };
+            // std430 : vec3 are aligned like vec4, arrays elements are not padded to vec4
+            let align = match components {
+                1 => 4,
+                2 => 8,
+                _ => 16,
+            };
+            for ident in field.identifiers.iter() {
+                let name = ident.ident.0.to_string();
+                let array = ident
+                    .array_spec
+                    .as_ref()
+                    .or(field.ty.array_specifier.as_ref());
+                let length = match array.map(|it| it.dimensions.as_slice()) {
+                    None => None,
+                    Some([dimension]) => match &dimension.content {
+                        ArraySpecifierDimensionData::ExplicitlySized(size) => match size.content {
+                            ExprData::IntConst(size) if size > 0 => Some(size as u32),
+                            ExprData::UIntConst(size) if size > 0 => Some(size),
+                            _ => {
+                                return Err(anyhow!(
+                                    "The size of output array {} must be a constant",
+                                    name
+                                ))
+                            }
+                        },
+                        ArraySpecifierDimensionData::Unsized => {
+                            return Err(anyhow!("Output array {} must be sized", name))
+                        }
+                    },
+                    Some(_) => {
+                        return Err(anyhow!("Output array {} has more than one dimension", name))
+                    }
+                };
+                offset = offset.next_multiple_of(align);
+                block_align = block_align.max(align);
+                fields.push(OutputField {
+                    name,
+                    scalar,
+                    components,
+                    length,
+                    offset,
+                    stride: align,
+                });
+                offset += match length {
+                    Some(length) => align * length,
+                    None => 4 * components,
+                };
+            }
+        }
+        if fields.is_empty() {
+            return Err(anyhow!("The output block {} is empty", block.name.0));
+        }
+
+        metadata.output = Some(OutputBlock {
+            name: block.name.0.to_string(),
+            fields,
+            size: offset.next_multiple_of(block_align) as u64,
+        });
+        // The layout qualifier is replaced, the buffer storage qualifier is kept
+        let layout = block
+            .qualifier
+            .qualifiers
+            .iter()
+            .position(|it| matches!(it.content, TypeQualifierSpecData::Layout(_)))
+            .unwrap();
+        block.qualifier.content.qualifiers.swap(0, layout);
+        convert_block_layout(block, "std430", 1, 1);
+    }
+    Ok(())
+}
+
+/// Replace the `layout(pass = name) uniform sampler2D id;` declarations with a channel bound to
+/// the output of the pass
+fn extract_buffers(ast: &mut TranslationUnit, metadata: &mut ShaderMetadata) -> Result<()> {
+    let mut decls = Vec::with_capacity(ast.0.len());
+    for decl in ast.0.drain(..) {
+        let (name, buffer) = match buffer_declaration(&decl) {
+            Some(it) => it,
+            None => {
+                decls.push(decl);
+                continue;
+            }
+        };
+        let channel = metadata.buffers.len();
+        if channel >= CHANNEL_COUNT {
+            return Err(anyhow!(
+                "Too many pass outputs sampled, only {} channels are available",
+                CHANNEL_COUNT
+            ));
+        }
+        metadata.buffers.push(buffer);
+        // Combined image samplers aren't supported by wgpu, the channel texture and sampler are
+        // declared separately and combined where the name is used
+        let channel_decls = parse(&format!(
+            "#include <NuanceChannels>\n#define {} sampler2D(nuanceChannel{}, nuanceChannelSampler{})\n",
+            name, channel, channel
+        ))?;
+        decls.extend(channel_decls.0);
+    }
+    ast.0 = decls;
+    Ok(())
+}
+
+/// Check if a declaration is a pass output declaration, returns the declared name
+fn buffer_declaration(decl: &ExternalDeclaration) -> Option<(String, BufferInput)> {
+    let list = match &decl.content {
+        ExternalDeclarationData::Declaration(decl) => match &decl.content {
+            DeclarationData::InitDeclaratorList(list) => &list.content,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if list.head.ty.ty.ty.content != TypeSpecifierNonArrayData::Sampler2D {
+        return None;
+    }
+    let name = list.head.name.as_ref()?.content.0.to_string();
+
+    let mut pass = None;
+    let mut previous = false;
+    for qualifier in list.head.ty.qualifier.as_ref()?.qualifiers.iter() {
+        if let TypeQualifierSpecData::Layout(layout) = &qualifier.content {
+            for id in layout.ids.iter() {
+                if let LayoutQualifierSpecData::Identifier(key, value) = &id.content {
+                    match (key.content.0.as_str(), value.as_ref().map(|it| &it.content)) {
+                        ("pass", Some(ExprData::Variable(value))) => {
+                            pass = Some(value.content.0.to_string())
+                        }
+                        ("previous", None) => previous = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    pass.map(|pass| (name, BufferInput { pass, previous }))
+}
 
-    let (mut ast, _, _) = ParseBuilder::<DefaultLexer, TranslationUnit>::new(source)
+fn parse(source: &str) -> Result<TranslationUnit> {
+    let (ast, _, _) = ParseBuilder::<DefaultLexer, TranslationUnit>::new(source)
         .opts(&ParseOptions {
             default_version: 460,
             target_vulkan: true,
@@ -399,12 +724,267 @@ pub fn extract(source: &str) -> Result<(ShaderMetadata, String)> {
         })
         .context(&ParseContext::new_with_comments())
         .parse()?;
+    Ok(ast)
+}
+
+/// Replace the `layout(texture = "path") uniform texture2D name;` declarations with a pragma.
+/// glsl-lang can't parse them (string and texture types). Declarations in comments are kept.
+fn extract_textures(source: &str, textures: &mut Vec<TextureInput>) -> Result<String> {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some((start, token)) = ["layout", "//", "/*"]
+        .iter()
+        .filter_map(|token| rest.find(token).map(|start| (start, *token)))
+        .min()
+    {
+        let (before, decl) = rest.split_at(start);
+        output.push_str(before);
+        if token != "layout" {
+            // Copy the comment up to its end
+            let end = if token == "//" {
+                decl.find('\n').unwrap_or(decl.len())
+            } else {
+                decl[2..].find("*/").map_or(decl.len(), |end| end + 4)
+            };
+            output.push_str(&decl[..end]);
+            rest = &decl[end..];
+            continue;
+        }
+        let in_identifier = before.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        match texture_declaration(decl)? {
+            Some((len, texture)) if !in_identifier => {
+                output.push_str(&format!(
+                    "\n#pragma {} {}\n",
+                    TEXTURE_PRAGMA,
+                    textures.len()
+                ));
+                textures.push(texture);
+                rest = &decl[len..];
+            }
+            _ => {
+                output.push_str("layout");
+                rest = &decl["layout".len()..];
+            }
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Replace the pragmas left by `extract_textures` with the declarations of the textures and their
+/// samplers, bound at set 3
+fn declare_textures(source: &str, textures: &[TextureInput]) -> String {
+    let mut output = String::with_capacity(source.len());
+    for line in source.lines() {
+        let texture = line
+            .trim()
+            .strip_prefix("#pragma")
+            .and_then(|it| it.trim_start().strip_prefix(TEXTURE_PRAGMA))
+            .filter(|it| it.starts_with(char::is_whitespace))
+            .and_then(|it| it.trim().parse::<usize>().ok())
+            .and_then(|i| textures.get(i).map(|texture| (i, texture)));
+        match texture {
+            Some((i, texture)) => output.push_str(&format!(
+                "layout(set = 3, binding = {}) uniform {} {};\nlayout(set = 3, binding = {}) uniform sampler {2}Sampler;",
+                2 * i,
+                texture.file.glsl_type(),
+                texture.name,
+                2 * i + 1
+            )),
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Rename the `output` qualifier of `layout(output)` so glsl-lang can parse it
+fn rename_output_qualifier(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("layout") {
+        let (before, decl) = rest.split_at(start + "layout".len());
+        output.push_str(before);
+        rest = decl;
+        let args = decl.trim_start();
+        if let Some(args) = args.strip_prefix('(') {
+            let args = args.trim_start();
+            let is_output = args.strip_prefix("output").is_some_and(|after| {
+                !after.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            });
+            if is_output {
+                // Keep everything up to the qualifier
+                let at = decl.len() - args.len();
+                output.push_str(&decl[..at]);
+                output.push_str(OUTPUT_QUALIFIER);
+                rest = &decl[at + "output".len()..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Parse a texture declaration, returns its length and the texture.
+/// Returns None if this is not a texture declaration.
+fn texture_declaration(decl: &str) -> Result<Option<(usize, TextureInput)>> {
+    let rest = match decl
+        .strip_prefix("layout")
+        .and_then(|it| it.trim_start().strip_prefix('('))
+    {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+    // The path may contain parenthesis or commas
+    let mut in_string = false;
+    let mut args = Vec::new();
+    let mut arg_start = 0;
+    let mut end = None;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' | ')' if !in_string => {
+                args.push(&rest[arg_start..i]);
+                arg_start = i + 1;
+                if c == ')' {
+                    end = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let end = match end {
+        Some(end) => end,
+        None => return Ok(None),
+    };
+    let args: Vec<(&str, &str)> = args
+        .iter()
+        .map(|arg| match arg.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+            None => (arg.trim(), ""),
+        })
+        .collect();
+    let path = match args.iter().find(|(key, _)| *key == "texture") {
+        Some((_, path)) => PathBuf::from(path),
+        None => return Ok(None),
+    };
+
+    let invalid = || {
+        anyhow!(
+            "Invalid texture declaration, expected layout(texture = \"path\") uniform texture2D name;"
+        )
+    };
+    let rest = rest[end + 1..]
+        .trim_start()
+        .strip_prefix("uniform")
+        .ok_or_else(invalid)?
+        .trim_start();
+    let (ty, rest) = ["texture2D", "textureCube", "texture3D"]
+        .iter()
+        .find_map(|ty| rest.strip_prefix(ty).map(|rest| (*ty, rest)))
+        .filter(|(_, rest)| rest.starts_with(char::is_whitespace))
+        .ok_or_else(invalid)?;
+    let rest = rest.trim_start();
+    let name_len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .ok_or_else(invalid)?;
+    let name = &rest[..name_len];
+    let rest = rest[name_len..]
+        .trim_start()
+        .strip_prefix(';')
+        .ok_or_else(invalid)?;
+    if name.is_empty() {
+        return Err(invalid());
+    }
+
+    if let Some((key, _)) = args
+        .iter()
+        .find(|(key, _)| !["texture", "width", "height", "depth", "voxel"].contains(key))
+    {
+        return Err(anyhow!("Unknown texture setting {} for {}", key, name));
+    }
+    let value = |key: &str| {
+        args.iter()
+            .find(|(it, _)| *it == key)
+            .map(|(_, value)| *value)
+    };
+    let dimension = |key: &str| {
+        value(key)
+            .map(|it| {
+                it.parse::<u32>()
+                    .map_err(|_| anyhow!("Invalid {} for texture {} : {}", key, name, it))
+            })
+            .transpose()
+    };
+    let file = match ty {
+        "texture2D" => TextureFile::Image(path),
+        "textureCube" => TextureFile::Cube(path),
+        _ => match (
+            dimension("width")?,
+            dimension("height")?,
+            dimension("depth")?,
+        ) {
+            (Some(width), Some(height), Some(depth)) => TextureFile::Volume {
+                path,
+                size: [width, height, depth],
+                voxel: match value("voxel").unwrap_or("u8") {
+                    "u8" => Voxel::U8,
+                    "u16" => Voxel::U16,
+                    "f32" => Voxel::F32,
+                    other => {
+                        return Err(anyhow!(
+                            "Invalid voxel type for texture {} : {}, expected u8, u16 or f32",
+                            name,
+                            other
+                        ))
+                    }
+                },
+            },
+            (None, None, None) if value("voxel").is_none() => TextureFile::Slices(path),
+            _ => {
+                return Err(anyhow!(
+                    "The raw volume {} needs a width, height and depth",
+                    name
+                ))
+            }
+        },
+    };
+    Ok(Some((
+        decl.len() - rest.len(),
+        TextureInput {
+            name: name.to_string(),
+            file,
+        },
+    )))
+}
+
+pub fn extract(source: &str) -> Result<(ShaderMetadata, String)> {
+    let mut metadata = ShaderMetadata::default();
+
+    let source = extract_textures(source, &mut metadata.textures)?;
+    let source = rename_output_qualifier(&source);
+    let mut ast = parse(&source)?;
 
     // Extract some ast juice
     ast.visit_mut(&mut metadata);
+    extract_buffers(&mut ast, &mut metadata)?;
+    extract_output(&mut ast, &mut metadata)?;
 
     let mut transpiled = String::new();
     show_translation_unit(&mut transpiled, &ast, FormattingState::default())?;
+    let mut transpiled = declare_textures(&transpiled, &metadata.textures);
+    if metadata.shadertoy && metadata.sound {
+        return Err(anyhow!(
+            "A shader can't declare both mainImage and mainSound, write them in separate files"
+        ));
+    }
+    if metadata.shadertoy {
+        transpiled = shadertoy::wrap_image(&transpiled);
+    }
+    if metadata.sound {
+        transpiled = sound::wrap_sound(&transpiled);
+    }
     debug!("{}", &transpiled);
     Ok((metadata, transpiled))
 }
@@ -445,3 +1025,102 @@ coerceconst_impl!(f32);
 coerceconst_impl!(f64);
 coerceconst_impl!(u32);
 coerceconst_impl!(i32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textures_in_comments_are_kept() {
+        let source = r#"
+// layout(texture = "line.png") uniform texture2D line;
+/* layout(texture = "block.png") uniform texture2D block;
+   layout(texture = "block2.png") uniform texture2D block2; */
+my_layout(texture = "word.png") uniform texture2D word;
+layout(texture = "used.png") uniform texture2D used;
+"#;
+        let mut textures = Vec::new();
+        let output = extract_textures(source, &mut textures).unwrap();
+        assert_eq!(textures.len(), 1);
+        assert_eq!(textures[0].name, "used");
+        assert!(output.contains("/* layout(texture = \"block.png\")"));
+        assert!(output.contains("my_layout(texture = \"word.png\")"));
+    }
+
+    #[test]
+    fn pragmas_match_whole_indices() {
+        let textures: Vec<TextureInput> = (0..11)
+            .map(|i| TextureInput {
+                name: format!("tex{}", i),
+                file: TextureFile::Image(PathBuf::from(format!("{}.png", i))),
+            })
+            .collect();
+        let source = format!(
+            "#pragma {0} 10\n#pragma {0} 1\n#pragma {0}s 2\n",
+            TEXTURE_PRAGMA
+        );
+        let output = declare_textures(&source, &textures);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].contains("binding = 20) uniform texture2D tex10;"));
+        assert!(lines[2].contains("binding = 2) uniform texture2D tex1;"));
+        assert_eq!(lines[4], format!("#pragma {}s 2", TEXTURE_PRAGMA));
+    }
+
+    fn output_block(source: &str) -> Result<Option<OutputBlock>> {
+        let mut ast = parse(&rename_output_qualifier(source))?;
+        let mut metadata = ShaderMetadata::default();
+        extract_output(&mut ast, &mut metadata)?;
+        Ok(metadata.output)
+    }
+
+    #[test]
+    fn output_std430_layout() {
+        let block = output_block(
+            "layout(output) buffer Stats {
+    float total;
+    vec3 color;
+    float values[3];
+    vec2 point;
+    uint counts[2];
+};",
+        )
+        .unwrap()
+        .unwrap();
+        let layout: Vec<(&str, u32, u32)> = block
+            .fields
+            .iter()
+            .map(|it| (it.name.as_str(), it.offset, it.stride))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                ("total", 0, 4),
+                ("color", 16, 16),
+                ("values", 28, 4),
+                ("point", 40, 8),
+                ("counts", 48, 4),
+            ]
+        );
+        // Rounded up to the alignment of vec3
+        assert_eq!(block.size, 64);
+    }
+
+    #[test]
+    fn output_vec4_arrays() {
+        let block = output_block("layout(output) buffer Points { vec4 points[2]; float last; };")
+            .unwrap()
+            .unwrap();
+        assert_eq!(block.fields[1].offset, 32);
+        assert_eq!(block.size, 48);
+    }
+
+    #[test]
+    fn output_must_be_a_buffer() {
+        assert!(output_block("layout(output) uniform Stats { float total; };").is_err());
+        assert!(
+            output_block("layout(std430) buffer Other { float total; };")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
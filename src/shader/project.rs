@@ -0,0 +1,88 @@
+//! Projects described by a `nuance.toml` manifest.
+//!
+//! A project lists the shader files of its passes, what their channels are bound to and some
+//! settings. Paths are relative to the manifest.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::{Filter, Wrap};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Fixed canvas resolution
+    pub resolution: Option<[u32; 2]>,
+    /// Target framerate
+    pub framerate: Option<f32>,
+    /// Directories searched for includes
+    #[serde(default)]
+    pub include_dirs: Vec<PathBuf>,
+    /// Passes in rendering order, the last one is displayed
+    #[serde(default, rename = "pass")]
+    pub passes: Vec<PassDecl>,
+    /// Overrides of the default values of the displayed pass params
+    #[serde(default)]
+    pub params: HashMap<String, ParamValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassDecl {
+    /// Name to reference the pass, defaults to the shader file name
+    pub name: Option<String>,
+    pub shader: PathBuf,
+    pub entry_point: Option<String>,
+    #[serde(default)]
+    pub channels: Vec<ChannelDecl>,
+}
+
+/// A channel is bound to a pass output, a texture, an audio file or nothing
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelDecl {
+    pub pass: Option<String>,
+    #[serde(default)]
+    pub previous: bool,
+    pub texture: Option<PathBuf>,
+    pub audio: Option<PathBuf>,
+    #[serde(default)]
+    pub filter: Filter,
+    #[serde(default)]
+    pub wrap: Wrap,
+    #[serde(default)]
+    pub mipmaps: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Bool(bool),
+    Number(f32),
+    Vector(Vec<f32>),
+}
+
+impl ParamValue {
+    pub fn values(&self) -> Vec<f32> {
+        match self {
+            ParamValue::Bool(value) => vec![*value as u32 as f32],
+            ParamValue::Number(value) => vec![*value],
+            ParamValue::Vector(values) => values.clone(),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Invalid project manifest : {}", e))?;
+        if manifest.passes.is_empty() {
+            return Err(anyhow!("The project has no pass"));
+        }
+        Ok(manifest)
+    }
+}
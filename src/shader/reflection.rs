@@ -0,0 +1,323 @@
+//! Extract shader metadata from a naga module, for the languages we can't preprocess ourselves.
+//!
+//! Those languages have no equivalent to the GLSL layout qualifiers, so param settings are written
+//! as annotations in comments : `// @range(0, 1) @init(0.5)`.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use log::error;
+use mint::{Vector2, Vector3};
+use naga::{
+    GlobalVariable, Handle, Module, ResourceBinding, ScalarKind, ShaderStage, StorageClass,
+    StructMember, Type, TypeInner, VectorSize,
+};
+
+use crate::Slider;
+
+/// The bind group the params block must be bound at
+pub const PARAMS_GROUP: u32 = 1;
+/// The binding the params block must be bound at
+pub const PARAMS_BINDING: u32 = 0;
+
+/// A setting written as `@name(args...)` in a comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub name: String,
+    pub args: Vec<f32>,
+}
+
+/// Parse all the annotations found in a comment
+pub fn parse_annotations(comment: &str) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find('@') {
+        rest = &rest[start + 1..];
+        let name_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = rest[..name_len].to_string();
+        rest = &rest[name_len..];
+
+        let mut args = Vec::new();
+        if let Some(inner) = rest.trim_start().strip_prefix('(') {
+            if let Some(end) = inner.find(')') {
+                args = inner[..end]
+                    .split(',')
+                    .filter(|it| !it.trim().is_empty())
+                    .filter_map(|it| match it.trim().parse() {
+                        Ok(value) => Some(value),
+                        Err(_) => {
+                            error!("Invalid value '{}' for annotation @{}", it.trim(), name);
+                            None
+                        }
+                    })
+                    .collect();
+                rest = &inner[end + 1..];
+            }
+        }
+
+        if !name.is_empty() {
+            annotations.push(Annotation { name, args });
+        }
+    }
+    annotations
+}
+
+/// Find the source code between the braces of a declaration like `struct Params {`
+pub fn find_block_body<'a>(source: &'a str, keyword: &str, name: &str) -> Option<&'a str> {
+    let mut rest = source;
+    while let Some(i) = rest.find(keyword) {
+        rest = &rest[i + keyword.len()..];
+        if let Some(decl) = rest.trim_start().strip_prefix(name) {
+            if let Some(decl) = decl.trim_start().strip_prefix('{') {
+                return decl.find('}').map(|end| &decl[..end]);
+            }
+        }
+    }
+    None
+}
+
+/// Collect the annotations on each member declared in a block body.
+/// Annotations are read from the comments on the same line or on the lines before a member.
+/// `member_name` extracts the member name from the code on a line, if any is declared.
+pub fn member_annotations<'a>(
+    body: &'a str,
+    member_name: impl Fn(&'a str) -> Option<&'a str>,
+) -> HashMap<String, Vec<Annotation>> {
+    let mut annotations = HashMap::new();
+    // Annotations in comments preceding a member
+    let mut pending = Vec::new();
+    for line in body.lines() {
+        let (code, comment) = match line.find("//") {
+            Some(i) => (&line[..i], &line[i + 2..]),
+            None => (line, ""),
+        };
+        pending.extend(parse_annotations(comment));
+
+        if let Some(name) = member_name(code) {
+            annotations.insert(name.to_string(), std::mem::take(&mut pending));
+        }
+    }
+    annotations
+}
+
+/// List the names of the fragment entry points of a module
+pub fn fragment_entry_points(module: &Module) -> Vec<String> {
+    module
+        .entry_points
+        .iter()
+        .filter(|it| it.stage == ShaderStage::Fragment)
+        .map(|it| it.name.clone())
+        .collect()
+}
+
+/// Find the params block, which is the uniform buffer bound at the well known binding
+pub fn find_params_block(module: &Module) -> Option<&GlobalVariable> {
+    module.global_variables.iter().find_map(|(_, var)| {
+        if var.class == StorageClass::Uniform
+            && var.binding
+                == Some(ResourceBinding {
+                    group: PARAMS_GROUP,
+                    binding: PARAMS_BINDING,
+                })
+        {
+            Some(var)
+        } else {
+            None
+        }
+    })
+}
+
+/// Create sliders from the members of the params struct, in declaration order
+pub fn create_sliders(
+    module: &Module,
+    ty: Handle<Type>,
+    annotations: &HashMap<String, Vec<Annotation>>,
+) -> Result<Vec<Slider>> {
+    match block_members(module, ty) {
+        Some(members) => members
+            .iter()
+            .map(|member| {
+                let name = member
+                    .name
+                    .clone()
+                    .ok_or_else(|| anyhow!("Params block members must be named"))?;
+                let annotations = annotations.get(&name).map(Vec::as_slice).unwrap_or(&[]);
+                create_slider(name, &module.types[member.ty].inner, annotations)
+            })
+            .collect(),
+        None => Err(anyhow!("The params block must be a struct")),
+    }
+}
+
+/// Get the members of a block struct.
+/// Some compilers wrap the block struct in another struct with a single unnamed member.
+pub fn block_members(module: &Module, ty: Handle<Type>) -> Option<&[StructMember]> {
+    match &module.types[ty].inner {
+        TypeInner::Struct { members, .. } => match members.as_slice() {
+            [member] if member.name.is_none() => block_members(module, member.ty),
+            members => Some(members),
+        },
+        _ => None,
+    }
+}
+
+/// Create a slider from a member type and its annotations
+pub fn create_slider(name: String, ty: &TypeInner, annotations: &[Annotation]) -> Result<Slider> {
+    let has = |key: &str| annotations.iter().any(|it| it.name == key);
+    let arg = |key: &str, index: usize| {
+        annotations
+            .iter()
+            .find(|it| it.name == key)
+            .and_then(|it| it.args.get(index).copied())
+    };
+    let check = |allowed: &[&str]| {
+        for annotation in annotations {
+            if !allowed.contains(&annotation.name.as_str()) {
+                error!(
+                    "Wrong slider setting : {} on param {}",
+                    annotation.name, name
+                );
+            }
+        }
+    };
+
+    match *ty {
+        // To Slider::Float
+        TypeInner::Scalar {
+            kind: ScalarKind::Float,
+            width: 4,
+        } => {
+            check(&["range", "min", "max", "init"]);
+            let init = arg("init", 0).unwrap_or(0.0);
+            Ok(Slider::Float {
+                min: arg("range", 0).or_else(|| arg("min", 0)).unwrap_or(0.0),
+                max: arg("range", 1).or_else(|| arg("max", 0)).unwrap_or(1.0),
+                value: init,
+                default: init,
+                name,
+            })
+        }
+        // To Slider::Bool if the bool annotation is set, bool isn't host shareable
+        TypeInner::Scalar {
+            kind: ScalarKind::Uint,
+            width: 4,
+        } if has("bool") => {
+            check(&["bool", "init"]);
+            let init = arg("init", 0).map(|it| (it != 0.0) as u32).unwrap_or(0);
+            Ok(Slider::Bool {
+                name,
+                value: init,
+                default: init,
+            })
+        }
+        // To Slider::Uint
+        TypeInner::Scalar {
+            kind: ScalarKind::Uint,
+            width: 4,
+        } => {
+            check(&["range", "min", "max", "init"]);
+            let init = arg("init", 0).unwrap_or(0.0) as u32;
+            Ok(Slider::Uint {
+                min: arg("range", 0).or_else(|| arg("min", 0)).unwrap_or(0.0) as u32,
+                max: arg("range", 1).or_else(|| arg("max", 0)).unwrap_or(100.0) as u32,
+                value: init,
+                default: init,
+                name,
+            })
+        }
+        TypeInner::Vector {
+            size: VectorSize::Bi,
+            kind: ScalarKind::Float,
+            width: 4,
+        } => {
+            check(&["init"]);
+            let init =
+                Vector2::from([arg("init", 0).unwrap_or(0.0), arg("init", 1).unwrap_or(0.0)]);
+            Ok(Slider::Vec2 {
+                name,
+                value: init,
+                default: init,
+            })
+        }
+        // To Slider::Color if the color annotation is set
+        TypeInner::Vector {
+            size: VectorSize::Tri,
+            kind: ScalarKind::Float,
+            width: 4,
+        } => {
+            check(&["color", "init"]);
+            let init = Vector3::from([
+                arg("init", 0).unwrap_or(0.0),
+                arg("init", 1).unwrap_or(0.0),
+                arg("init", 2).unwrap_or(0.0),
+            ]);
+            Ok(if has("color") {
+                Slider::Color {
+                    name,
+                    value: init,
+                    default: init,
+                }
+            } else {
+                Slider::Vec3 {
+                    name,
+                    value: init,
+                    default: init,
+                }
+            })
+        }
+        _ => Err(anyhow!("Unsupported type for param {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotations_with_args() {
+        assert_eq!(
+            parse_annotations(" @range(0, 1) @init(0.5) @color"),
+            vec![
+                Annotation {
+                    name: "range".to_string(),
+                    args: vec![0.0, 1.0],
+                },
+                Annotation {
+                    name: "init".to_string(),
+                    args: vec![0.5],
+                },
+                Annotation {
+                    name: "color".to_string(),
+                    args: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_args_are_skipped() {
+        let annotations = parse_annotations("@range(0, one, 2) @");
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].args, vec![0.0, 2.0]);
+    }
+
+    #[test]
+    fn annotations_before_and_after_members() {
+        let source = "struct Params {
+    // @color
+    // @init(1, 0, 0)
+    color: vec3<f32>;
+    intensity: f32; // @range(0, 1) @init(1)
+    plain: f32;
+};";
+        let body = find_block_body(source, "struct", "Params").unwrap();
+        let annotations =
+            member_annotations(body, |code| code.split_once(':').map(|(it, _)| it.trim()));
+        assert_eq!(annotations["color"].len(), 2);
+        assert_eq!(annotations["intensity"][0].args, vec![0.0, 1.0]);
+        assert_eq!(annotations["intensity"][1].args, vec![1.0]);
+        assert!(annotations["plain"].is_empty());
+    }
+}
@@ -1,8 +1,12 @@
+use crevice::std430::AsStd430;
 use lazy_static::lazy_static;
 use wgpu::*;
 
+use crate::{ColorSpace, Globals, TargetFormat, TextureFile, Topology, VertexSettings};
+
 lazy_static! {
     static ref VERTEX_SHADER_DESC: ShaderModuleDescriptor<'static> = include_wgsl!("quad.wgsl");
+    static ref DISPLAY_SHADER_DESC: ShaderModuleDescriptor<'static> = include_wgsl!("display.wgsl");
 }
 
 unsafe fn get_vertex_shader_module(device: &Device) -> &'static ShaderModule {
@@ -10,68 +14,214 @@ unsafe fn get_vertex_shader_module(device: &Device) -> &'static ShaderModule {
     &*VERTEX_SHADER_MOD.get_or_insert_with(|| device.create_shader_module(&VERTEX_SHADER_DESC))
 }
 
+/// Format of the texture the displayed pass renders to.
+/// Without sRGB encoding, colors are stored as written in a linear format.
+pub fn target_texture_format(
+    target: TargetFormat,
+    color_space: ColorSpace,
+    surface: TextureFormat,
+) -> TextureFormat {
+    match target {
+        TargetFormat::Surface if color_space == ColorSpace::Passthrough => match surface {
+            TextureFormat::Bgra8UnormSrgb => TextureFormat::Bgra8Unorm,
+            TextureFormat::Rgba8UnormSrgb => TextureFormat::Rgba8Unorm,
+            other => other,
+        },
+        TargetFormat::Surface => surface,
+        TargetFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        TargetFormat::Rgba32Float => TextureFormat::Rgba32Float,
+    }
+}
+
+/// Format of the storage image written by compute shaders, 8 bits storage images are linear
+pub fn storage_texture_format(target: TargetFormat) -> TextureFormat {
+    match target {
+        TargetFormat::Surface => TextureFormat::Rgba8Unorm,
+        TargetFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        TargetFormat::Rgba32Float => TextureFormat::Rgba32Float,
+    }
+}
+
+/// Size of the workgroups of compute shaders, as declared in the compute header
+pub const WORKGROUP_SIZE: u32 = 8;
+
+/// Stages sampling the channels and declared textures
+const SAMPLING_STAGES: ShaderStages = ShaderStages::all();
+
+/// Number of texture channels a shader can sample
+pub const CHANNEL_COUNT: usize = 4;
+
+/// Layout of the channels bind group (set 2).
+/// Channel i texture is at binding 2 * i and its sampler at binding 2 * i + 1.
+pub fn create_channels_bind_group_layout(device: &Device) -> BindGroupLayout {
+    let entries: Vec<BindGroupLayoutEntry> = (0..CHANNEL_COUNT as u32)
+        .flat_map(|i| {
+            [
+                BindGroupLayoutEntry {
+                    binding: 2 * i,
+                    visibility: SAMPLING_STAGES,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2 * i + 1,
+                    visibility: SAMPLING_STAGES,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ]
+        })
+        .collect();
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("channels bind group layout"),
+        entries: &entries,
+    })
+}
+
+/// Dimension of the view of a texture declared in a shader
+pub fn texture_view_dimension(texture: &TextureFile) -> TextureViewDimension {
+    match texture {
+        TextureFile::Image(_) => TextureViewDimension::D2,
+        TextureFile::Cube(_) => TextureViewDimension::Cube,
+        TextureFile::Volume { .. } | TextureFile::Slices(_) => TextureViewDimension::D3,
+    }
+}
+
+/// Layout of the textures bind group (set 3) with the textures declared in a shader.
+/// Texture i is at binding 2 * i and its sampler at binding 2 * i + 1.
+pub fn create_textures_bind_group_layout(
+    device: &Device,
+    textures: &[TextureFile],
+) -> BindGroupLayout {
+    let entries: Vec<BindGroupLayoutEntry> = (0..textures.len() as u32)
+        .zip(textures.iter())
+        .flat_map(|(i, texture)| {
+            [
+                BindGroupLayoutEntry {
+                    binding: 2 * i,
+                    visibility: SAMPLING_STAGES,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: texture_view_dimension(texture),
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2 * i + 1,
+                    visibility: SAMPLING_STAGES,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ]
+        })
+        .collect();
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("textures bind group layout"),
+        entries: &entries,
+    })
+}
+
+/// Create the params uniform buffer and its bind group (set 1) with the output storage buffer.
+/// The bind group is empty when there is no params and no output.
+fn create_params_bind_group(
+    device: &Device,
+    params_buffer_size: u64,
+    output_buffer: Option<&Buffer>,
+    visibility: ShaderStages,
+) -> (Option<Buffer>, BindGroupLayout, BindGroup) {
+    let params_buffer = if params_buffer_size > 0 {
+        Some(device.create_buffer(&BufferDescriptor {
+            label: Some("params ubo"),
+            size: params_buffer_size,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }))
+    } else {
+        None
+    };
+
+    let buffers = params_buffer
+        .iter()
+        .map(|buffer| (0, BufferBindingType::Uniform, buffer))
+        .chain(
+            output_buffer
+                .map(|buffer| (1, BufferBindingType::Storage { read_only: false }, buffer)),
+        );
+    let layout_entries: Vec<BindGroupLayoutEntry> = buffers
+        .clone()
+        .map(|(binding, ty, _)| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+        .collect();
+    let params_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("main bind group layout"),
+        entries: &layout_entries,
+    });
+
+    let entries: Vec<BindGroupEntry> = buffers
+        .map(|(binding, _, buffer)| BindGroupEntry {
+            binding,
+            resource: BindingResource::Buffer(BufferBinding {
+                buffer,
+                offset: 0,
+                size: None,
+            }),
+        })
+        .collect();
+    let params_bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("main bind group"),
+        layout: &params_bind_group_layout,
+        entries: &entries,
+    });
+    (params_buffer, params_bind_group_layout, params_bind_group)
+}
+
 pub struct ShaderRenderPass {
-    params_bind_group: Option<BindGroup>,
+    params_bind_group: BindGroup,
     params_buffer: Option<Buffer>,
     pipeline: RenderPipeline,
 }
 
 impl ShaderRenderPass {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &Device,
         shader_source: &ShaderModule,
+        entry_point: &str,
         last_tex_layout: &BindGroupLayout,
+        channels_layout: &BindGroupLayout,
+        textures_layout: &BindGroupLayout,
         push_constants_size: u32,
         params_buffer_size: u64,
+        output_buffer: Option<&Buffer>,
         format: TextureFormat,
     ) -> Self {
-        let bind_group_layout;
-        let params_buffer;
-        let params_bind_group;
-        if params_buffer_size > 0 {
-            bind_group_layout = Some(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("main bind group layout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            }));
-
-            params_buffer = Some(device.create_buffer(&BufferDescriptor {
-                label: Some("params ubo"),
-                size: params_buffer_size,
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            }));
-
-            params_bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
-                label: Some("main bind group"),
-                layout: bind_group_layout.as_ref().unwrap(),
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::Buffer(BufferBinding {
-                        buffer: params_buffer.as_ref().unwrap(),
-                        offset: 0,
-                        size: None,
-                    }),
-                }],
-            }));
-        } else {
-            bind_group_layout = None;
-            params_buffer = None;
-            params_bind_group = None;
-        }
+        // The channels are at set 2
+        let (params_buffer, params_bind_group_layout, params_bind_group) = create_params_bind_group(
+            device,
+            params_buffer_size,
+            output_buffer,
+            ShaderStages::FRAGMENT,
+        );
 
-        let mut layouts = vec![last_tex_layout];
-        if let Some(layout) = &bind_group_layout {
-            layouts.push(layout);
-        }
+        let layouts = [
+            last_tex_layout,
+            &params_bind_group_layout,
+            channels_layout,
+            textures_layout,
+        ];
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("nuance shader pipeline layout"),
@@ -110,7 +260,7 @@ impl ShaderRenderPass {
             },
             fragment: Some(FragmentState {
                 module: shader_source,
-                entry_point: "main",
+                entry_point,
                 targets: &[ColorTargetState {
                     format,
                     write_mask: ColorWrites::ALL,
@@ -140,6 +290,8 @@ impl ShaderRenderPass {
         output_tex: &TextureView,
         push_constants: &[u8],
         last_tex: &BindGroup,
+        channels: &BindGroup,
+        textures: &BindGroup,
     ) {
         puffin::profile_scope!("shader pass execute");
 
@@ -156,9 +308,9 @@ impl ShaderRenderPass {
             depth_stencil_attachment: None,
         });
         rpass.set_bind_group(0, last_tex, &[]);
-        if let Some(bind_group) = &self.params_bind_group {
-            rpass.set_bind_group(1, bind_group, &[]);
-        }
+        rpass.set_bind_group(1, &self.params_bind_group, &[]);
+        rpass.set_bind_group(2, channels, &[]);
+        rpass.set_bind_group(3, textures, &[]);
         rpass.set_pipeline(&self.pipeline);
         // Push constants mapped to uniform block
         rpass.set_push_constants(ShaderStages::FRAGMENT, 0, push_constants);
@@ -167,3 +319,580 @@ impl ShaderRenderPass {
         rpass.draw(0..3, 0..1);
     }
 }
+
+/// Runs a compute shader writing to a storage image the size of the canvas
+pub struct ComputePass {
+    /// Previous frame, its sampler and the output image (set 0)
+    output_bind_group_layout: BindGroupLayout,
+    params_bind_group: BindGroup,
+    params_buffer: Option<Buffer>,
+    pipeline: ComputePipeline,
+}
+
+impl ComputePass {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        shader_source: &ShaderModule,
+        entry_point: &str,
+        previous_filtering: bool,
+        channels_layout: &BindGroupLayout,
+        textures_layout: &BindGroupLayout,
+        push_constants_size: u32,
+        params_buffer_size: u64,
+        output_buffer: Option<&Buffer>,
+        format: TextureFormat,
+    ) -> Self {
+        let output_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("compute output bind group layout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float {
+                                filterable: previous_filtering,
+                            },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Sampler(if previous_filtering {
+                            SamplerBindingType::Filtering
+                        } else {
+                            SamplerBindingType::NonFiltering
+                        }),
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let (params_buffer, params_bind_group_layout, params_bind_group) = create_params_bind_group(
+            device,
+            params_buffer_size,
+            output_buffer,
+            ShaderStages::COMPUTE,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("nuance compute pipeline layout"),
+            bind_group_layouts: &[
+                &output_bind_group_layout,
+                &params_bind_group_layout,
+                channels_layout,
+                textures_layout,
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..push_constants_size,
+            }],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("nuance compute pipeline"),
+            layout: Some(&pipeline_layout),
+            module: shader_source,
+            entry_point,
+        });
+
+        Self {
+            output_bind_group_layout,
+            params_bind_group,
+            params_buffer,
+            pipeline,
+        }
+    }
+
+    /// Create the bind group of the previous frame and the output image
+    pub fn create_output_bind_group(
+        &self,
+        device: &Device,
+        last_tex: &TextureView,
+        last_tex_sampler: &Sampler,
+        output_tex: &TextureView,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("compute output bind group"),
+            layout: &self.output_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(last_tex),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(last_tex_sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(output_tex),
+                },
+            ],
+        })
+    }
+
+    pub fn update_buffers(&self, queue: &Queue, params_buffer: &[u8]) {
+        if let Some(buffer) = &self.params_buffer {
+            queue.write_buffer(buffer, 0, params_buffer);
+        }
+    }
+
+    /// Dispatch enough workgroups to cover an output of the given size
+    pub fn execute(
+        &self,
+        encoder: &mut CommandEncoder,
+        size: [u32; 2],
+        push_constants: &[u8],
+        output: &BindGroup,
+        channels: &BindGroup,
+        textures: &BindGroup,
+    ) {
+        puffin::profile_scope!("compute pass execute");
+
+        let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("main compute pass"),
+        });
+        cpass.set_bind_group(0, output, &[]);
+        cpass.set_bind_group(1, &self.params_bind_group, &[]);
+        cpass.set_bind_group(2, channels, &[]);
+        cpass.set_bind_group(3, textures, &[]);
+        cpass.set_pipeline(&self.pipeline);
+        cpass.set_push_constants(0, push_constants);
+        cpass.dispatch(
+            size[0].div_ceil(WORKGROUP_SIZE),
+            size[1].div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
+
+/// Whether primitives can be blended and multisampled on a texture of this format,
+/// Rgba32Float textures can't
+fn is_blendable(format: TextureFormat) -> bool {
+    format.describe().guaranteed_format_features.filterable
+}
+
+/// Number of samples of the texture a vertex shader draws to before it is resolved
+pub fn vertex_sample_count(settings: VertexSettings, format: TextureFormat) -> u32 {
+    if settings.msaa && is_blendable(format) {
+        4
+    } else {
+        1
+    }
+}
+
+/// Offset of `uVertexCount` in the push constants of vertex shaders, right after the globals.
+/// Vertex shaders are compiled with it as `NUANCE_VERTEX_COUNT_OFFSET`.
+pub fn vertex_count_offset() -> u32 {
+    Globals::std430_size_static() as u32
+}
+
+/// Size of the push constants of vertex shaders : the globals and the vertex count
+pub fn vertex_push_constants_size() -> u32 {
+    vertex_count_offset() + 4
+}
+
+/// Draws the primitives of a vertex shader over a cleared target
+pub struct VertexPass {
+    params_bind_group: BindGroup,
+    params_buffer: Option<Buffer>,
+    pipeline: RenderPipeline,
+}
+
+impl VertexPass {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        (vertex_module, fragment_module): (&ShaderModule, &ShaderModule),
+        last_tex_layout: &BindGroupLayout,
+        channels_layout: &BindGroupLayout,
+        textures_layout: &BindGroupLayout,
+        push_constants_size: u32,
+        params_buffer_size: u64,
+        settings: VertexSettings,
+        format: TextureFormat,
+    ) -> Self {
+        debug_assert!(push_constants_size <= vertex_count_offset());
+        let (params_buffer, params_bind_group_layout, params_bind_group) = create_params_bind_group(
+            device,
+            params_buffer_size,
+            None,
+            ShaderStages::VERTEX_FRAGMENT,
+        );
+
+        let layouts = [
+            last_tex_layout,
+            &params_bind_group_layout,
+            channels_layout,
+            textures_layout,
+        ];
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("nuance vertex pipeline layout"),
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::VERTEX_FRAGMENT,
+                range: 0..vertex_push_constants_size(),
+            }],
+        });
+
+        let topology = match settings.topology {
+            Topology::Points => PrimitiveTopology::PointList,
+            Topology::Lines => PrimitiveTopology::LineList,
+            Topology::LineStrip => PrimitiveTopology::LineStrip,
+            Topology::Triangles => PrimitiveTopology::TriangleList,
+            Topology::TriangleStrip => PrimitiveTopology::TriangleStrip,
+        };
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("nuance vertex pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: vertex_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: vertex_sample_count(settings, format),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(FragmentState {
+                module: fragment_module,
+                entry_point: "main",
+                targets: &[ColorTargetState {
+                    format,
+                    write_mask: ColorWrites::ALL,
+                    blend: (settings.additive && is_blendable(format)).then_some(BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                }],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            params_bind_group,
+            params_buffer,
+            pipeline,
+        }
+    }
+
+    pub fn update_buffers(&self, queue: &Queue, params_buffer: &[u8]) {
+        if let Some(buffer) = &self.params_buffer {
+            queue.write_buffer(buffer, 0, params_buffer);
+        }
+    }
+
+    /// Draw the vertices to the output texture, through the multisampled texture if there is one
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute(
+        &self,
+        encoder: &mut CommandEncoder,
+        (output_tex, msaa_tex): (&TextureView, Option<&TextureView>),
+        count: u32,
+        push_constants: &[u8],
+        last_tex: &BindGroup,
+        channels: &BindGroup,
+        textures: &BindGroup,
+    ) {
+        puffin::profile_scope!("vertex pass execute");
+
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("main vertex pass"),
+            color_attachments: &[RenderPassColorAttachment {
+                view: msaa_tex.unwrap_or(output_tex),
+                resolve_target: msaa_tex.map(|_| output_tex),
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_bind_group(0, last_tex, &[]);
+        rpass.set_bind_group(1, &self.params_bind_group, &[]);
+        rpass.set_bind_group(2, channels, &[]);
+        rpass.set_bind_group(3, textures, &[]);
+        rpass.set_pipeline(&self.pipeline);
+        // The vertex count follows the globals
+        let mut constants = push_constants.to_vec();
+        constants.resize(vertex_count_offset() as usize, 0);
+        constants.extend_from_slice(&count.to_ne_bytes());
+        rpass.set_push_constants(ShaderStages::VERTEX_FRAGMENT, 0, &constants);
+        rpass.draw(0..count, 0..1);
+    }
+}
+
+/// Format of the target a block of samples is rendered to, a stereo sample per texel
+pub const SOUND_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rg32Float;
+
+/// Size of the push constants of sound shaders : sample offset, sample rate and block width
+const SOUND_PUSH_CONSTANTS_SIZE: u32 = 12;
+
+/// Renders a block of samples of a sound shader
+pub struct SoundPass {
+    pipeline: RenderPipeline,
+}
+
+impl SoundPass {
+    pub fn new(device: &Device, shader_source: &ShaderModule) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("sound pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::FRAGMENT,
+                range: 0..SOUND_PUSH_CONSTANTS_SIZE,
+            }],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("sound pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: unsafe { get_vertex_shader_module(device) },
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: shader_source,
+                entry_point: "main",
+                targets: &[ColorTargetState {
+                    format: SOUND_TEXTURE_FORMAT,
+                    write_mask: ColorWrites::ALL,
+                    blend: None,
+                }],
+            }),
+            multiview: None,
+        });
+
+        Self { pipeline }
+    }
+
+    pub fn execute(
+        &self,
+        encoder: &mut CommandEncoder,
+        output_tex: &TextureView,
+        push_constants: &[u8],
+    ) {
+        puffin::profile_scope!("sound pass execute");
+
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("sound render pass"),
+            color_attachments: &[RenderPassColorAttachment {
+                view: output_tex,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_push_constants(ShaderStages::FRAGMENT, 0, push_constants);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+/// Tonemapping operator applied when displaying a float target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tonemap {
+    #[default]
+    None,
+    Reinhard,
+    Aces,
+}
+
+/// How a float target is converted to the surface format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DisplaySettings {
+    pub tonemap: Tonemap,
+    /// Clamp colors to 0-1
+    pub clamp: bool,
+}
+
+/// Converts the displayed pass output to the surface format, with optional tonemapping
+pub struct DisplayPass {
+    bind_group_layout: BindGroupLayout,
+    settings_buffer: Buffer,
+    pipeline: RenderPipeline,
+}
+
+impl DisplayPass {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("display bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    // Float32 textures can't be filtered, texels are loaded directly
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let settings_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("display settings ubo"),
+            size: 16,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("display pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(&DISPLAY_SHADER_DESC);
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("display pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: unsafe { get_vertex_shader_module(device) },
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: "main",
+                targets: &[ColorTargetState {
+                    format,
+                    write_mask: ColorWrites::ALL,
+                    blend: None,
+                }],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            bind_group_layout,
+            settings_buffer,
+            pipeline,
+        }
+    }
+
+    /// Create the bind group to display a target
+    pub fn create_bind_group(&self, device: &Device, input: &TextureView) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("display bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(input),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.settings_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn update_buffers(
+        &self,
+        queue: &Queue,
+        settings: DisplaySettings,
+        color_space: ColorSpace,
+    ) {
+        let values = [
+            settings.tonemap as u32,
+            settings.clamp as u32,
+            (color_space == ColorSpace::Passthrough) as u32,
+            0,
+        ];
+        let mut data = [0; 16];
+        for (bytes, value) in data.chunks_exact_mut(4).zip(values) {
+            bytes.copy_from_slice(&value.to_ne_bytes());
+        }
+        queue.write_buffer(&self.settings_buffer, 0, &data);
+    }
+
+    pub fn execute(
+        &self,
+        encoder: &mut CommandEncoder,
+        output_tex: &TextureView,
+        input: &BindGroup,
+    ) {
+        puffin::profile_scope!("display pass execute");
+
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("display render pass"),
+            color_attachments: &[RenderPassColorAttachment {
+                view: output_tex,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_bind_group(0, input, &[]);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_count_follows_the_globals() {
+        let globals_size = Globals::std430_size_static() as u32;
+        assert!(vertex_count_offset() >= globals_size);
+        assert_eq!(vertex_count_offset() % 4, 0);
+        assert_eq!(vertex_push_constants_size(), vertex_count_offset() + 4);
+    }
+}
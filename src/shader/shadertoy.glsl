@@ -0,0 +1,29 @@
+// Shadertoy compatibility header
+// Shadertoy origin is the bottom left, the output is rendered upside down and flipped back when
+// displayed. This way, texture coordinates also follow the Shadertoy conventions.
+
+#define NUANCE
+#define NUANCE_SHADERTOY
+
+#version 460
+
+#include <NuanceInterface>
+
+#include <NuanceChannels>
+
+#define iResolution vec3(uResolution, 1.0)
+#define iTime fTime
+#define iTimeDelta fTimeDelta
+#define iFrameRate (1.0 / fTimeDelta)
+#define iFrame int(uFrame)
+// No click information, zw is always 0
+#define iMouse vec4(uMouse.x, float(uResolution.y) - uMouse.y, 0.0, 0.0)
+#define iDate vDate
+#define iSampleRate 44100.0
+
+#define iChannel0 sampler2D(nuanceChannel0, nuanceChannelSampler0)
+#define iChannel1 sampler2D(nuanceChannel1, nuanceChannelSampler1)
+#define iChannel2 sampler2D(nuanceChannel2, nuanceChannelSampler2)
+#define iChannel3 sampler2D(nuanceChannel3, nuanceChannelSampler3)
+#define iChannelTime float[4](fTime, fTime, fTime, fTime)
+#define iChannelResolution vec3[4](vec3(textureSize(iChannel0, 0), 1.0), vec3(textureSize(iChannel1, 0), 1.0), vec3(textureSize(iChannel2, 0), 1.0), vec3(textureSize(iChannel3, 0), 1.0))
@@ -0,0 +1,227 @@
+//! Compatibility with shaders written for [Shadertoy](https://www.shadertoy.com).
+//!
+//! A Shadertoy shader is detected by its `mainImage(out vec4, in vec2)` function. It is compiled
+//! with a header providing the Shadertoy uniforms and a generated `main()` calling `mainImage`.
+//!
+//! Shaders exported as JSON can also be imported with all their passes.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::renderer::CHANNEL_COUNT;
+use crate::{Channel, ChannelSampler, Filter, Wrap};
+
+/// Name of the Shadertoy entry function
+pub const MAIN_IMAGE: &str = "mainImage";
+
+/// Add the Shadertoy header and the wrapper main to a Shadertoy image shader source
+pub fn wrap_image(source: &str) -> String {
+    format!(
+        r#"#include <Shadertoy>
+
+{}
+
+void main() {{
+    mainImage(fragColor, gl_FragCoord.xy);
+    // The image pass alpha is ignored on Shadertoy, buffers keep it
+#ifndef NUANCE_SHADERTOY_BUFFER
+    fragColor.a = 1.0;
+#endif
+}}
+"#,
+        source
+    )
+}
+
+/// A pass of an imported Shadertoy shader
+pub struct ImportedPass {
+    pub name: String,
+    /// Source code of the pass, preceded by the common code
+    pub code: String,
+    pub channels: [Channel; CHANNEL_COUNT],
+    pub samplers: [ChannelSampler; CHANNEL_COUNT],
+}
+
+/// The API wraps the shader in an object, the website exports an array of shaders
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Api {
+        #[serde(rename = "Shader")]
+        shader: ShaderExport,
+    },
+    List(Vec<ShaderExport>),
+    Single(ShaderExport),
+}
+
+#[derive(Deserialize)]
+struct ShaderExport {
+    renderpass: Vec<RenderPass>,
+}
+
+#[derive(Deserialize)]
+struct RenderPass {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    inputs: Vec<Input>,
+    #[serde(default)]
+    outputs: Vec<Output>,
+}
+
+#[derive(Deserialize)]
+struct Input {
+    id: Value,
+    channel: usize,
+    /// Older exports name the input type ctype
+    #[serde(default)]
+    ctype: Option<String>,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    sampler: Option<Sampler>,
+}
+
+impl Input {
+    fn kind(&self) -> &str {
+        self.kind
+            .as_deref()
+            .or(self.ctype.as_deref())
+            .unwrap_or("unknown")
+    }
+}
+
+/// Sampler settings of an input, the values are strings
+#[derive(Deserialize)]
+struct Sampler {
+    #[serde(default)]
+    filter: Option<String>,
+    #[serde(default)]
+    wrap: Option<String>,
+}
+
+impl Sampler {
+    /// The channel sampler with these settings, unknown values are reported and left to their
+    /// default. vflip is not read : Shadertoy only flips images, buffers are sampled as rendered.
+    fn channel_sampler(&self, pass: &str, channel: usize) -> ChannelSampler {
+        let mut sampler = ChannelSampler::default();
+        match self.filter.as_deref() {
+            Some("nearest") => sampler.filter = Filter::Nearest,
+            Some("linear") | None => {}
+            Some("mipmap") => sampler.mipmaps = true,
+            Some(other) => warn!(
+                "Pass {} : unknown filter {} for iChannel{}, it will be linear",
+                pass, other, channel
+            ),
+        }
+        match self.wrap.as_deref() {
+            Some("clamp") | None => {}
+            Some("repeat") => sampler.wrap = Wrap::Repeat,
+            Some(other) => warn!(
+                "Pass {} : unknown wrap mode {} for iChannel{}, it will be clamped",
+                pass, other, channel
+            ),
+        }
+        sampler
+    }
+}
+
+#[derive(Deserialize)]
+struct Output {
+    id: Value,
+}
+
+/// Read the passes of a Shadertoy JSON export, in rendering order : Buffer A to D then Image.
+/// Unsupported passes and inputs are reported as warnings and ignored.
+pub fn import(json: &str) -> Result<Vec<ImportedPass>> {
+    let export: Export =
+        serde_json::from_str(json).map_err(|e| anyhow!("Invalid Shadertoy export : {}", e))?;
+    let shader = match export {
+        Export::Api { shader } | Export::Single(shader) => shader,
+        Export::List(list) => list
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("The Shadertoy export contains no shader"))?,
+    };
+
+    let mut common = String::new();
+    let mut buffers = Vec::new();
+    let mut image = None;
+    for pass in shader.renderpass {
+        match pass.kind.as_str() {
+            "common" => {
+                common.push_str(&pass.code);
+                common.push('\n');
+            }
+            "buffer" => buffers.push(pass),
+            "image" => image = Some(pass),
+            other => warn!("Pass {} ({}) is not supported, skipped", pass.name, other),
+        }
+    }
+    // Buffers are rendered in alphabetical order
+    buffers.sort_by(|a, b| a.name.cmp(&b.name));
+    let image = image.ok_or_else(|| anyhow!("The Shadertoy export has no image pass"))?;
+    let passes: Vec<RenderPass> = buffers.into_iter().chain(Some(image)).collect();
+
+    // Inputs reference the output ids of the passes they sample
+    let outputs: HashMap<String, usize> = passes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, pass)| pass.outputs.iter().map(move |it| (it.id.to_string(), i)))
+        .collect();
+
+    Ok(passes
+        .iter()
+        .enumerate()
+        .map(|(i, pass)| {
+            let mut channels: [Channel; CHANNEL_COUNT] = Default::default();
+            let mut samplers: [ChannelSampler; CHANNEL_COUNT] = Default::default();
+            for input in pass.inputs.iter() {
+                if input.channel >= CHANNEL_COUNT {
+                    warn!("Pass {} : invalid channel {}", pass.name, input.channel);
+                    continue;
+                }
+                match (input.kind(), outputs.get(&input.id.to_string())) {
+                    // Passes rendered later in the frame are sampled from the last frame
+                    ("buffer", Some(&index)) => {
+                        channels[input.channel] = Channel::Pass {
+                            index,
+                            previous: index >= i,
+                        };
+                        if let Some(sampler) = &input.sampler {
+                            samplers[input.channel] =
+                                sampler.channel_sampler(&pass.name, input.channel);
+                        }
+                    }
+                    ("buffer", None) => warn!(
+                        "Pass {} : iChannel{} samples an unknown buffer, it will be empty",
+                        pass.name, input.channel
+                    ),
+                    (kind, _) => warn!(
+                        "Pass {} : {} inputs are not supported, iChannel{} will be empty",
+                        pass.name, kind, input.channel
+                    ),
+                }
+            }
+            let define = if pass.kind == "buffer" {
+                "#define NUANCE_SHADERTOY_BUFFER 1\n"
+            } else {
+                ""
+            };
+            ImportedPass {
+                name: pass.name.clone(),
+                code: format!("{}{}{}", define, common, pass.code),
+                channels,
+                samplers,
+            }
+        })
+        .collect())
+}
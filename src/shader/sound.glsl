@@ -0,0 +1,20 @@
+// Sound shaders header
+// mainSound is evaluated for a block of samples, each texel of the target is a stereo sample.
+
+#define NUANCE
+#define NUANCE_SOUND
+
+#version 460
+
+layout(push_constant) uniform SoundGlobals {
+// Index of the first sample of the block
+    uint uSampleOffset;
+// Number of samples per second
+    uint uSampleRate;
+// Number of samples per row of the block
+    uint uBlockWidth;
+};
+
+#define iSampleRate float(uSampleRate)
+
+layout(location = 0) out vec2 nuanceSound;
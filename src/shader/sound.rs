@@ -0,0 +1,84 @@
+//! Sound shaders, written like the Sound tab of Shadertoy.
+//!
+//! A sound shader declares `vec2 mainSound(int samp, float time)` returning the left and right
+//! samples in -1-1. It is rendered in blocks : each texel of a float target is a stereo sample.
+//! The samples are written to a WAV file.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Name of the sound entry function
+pub const MAIN_SOUND: &str = "mainSound";
+
+/// Size of the target a block of samples is rendered to, one sample per texel
+pub const SOUND_BLOCK_SIZE: [u32; 2] = [1024, 256];
+
+/// Duration and sample rate of a rendered sound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundSettings {
+    /// Duration in sec
+    pub duration: f32,
+    /// Number of samples per second
+    pub sample_rate: u32,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            duration: 10.0,
+            sample_rate: 44100,
+        }
+    }
+}
+
+impl SoundSettings {
+    /// Number of stereo samples
+    pub fn sample_count(&self) -> u32 {
+        (self.duration.max(0.0) * self.sample_rate as f32).round() as u32
+    }
+
+    /// The push constants of the block starting at the given sample
+    pub fn block_push_constants(&self, offset: u32) -> [u8; 12] {
+        let mut data = [0; 12];
+        for (bytes, value) in
+            data.chunks_exact_mut(4)
+                .zip([offset, self.sample_rate, SOUND_BLOCK_SIZE[0]])
+        {
+            bytes.copy_from_slice(&value.to_ne_bytes());
+        }
+        data
+    }
+}
+
+/// Add the sound header and the wrapper main to a sound shader source
+pub fn wrap_sound(source: &str) -> String {
+    format!(
+        r#"#include <NuanceSound>
+
+{}
+
+void main() {{
+    uint index = uSampleOffset + uint(gl_FragCoord.y) * uBlockWidth + uint(gl_FragCoord.x);
+    nuanceSound = clamp(mainSound(int(index), float(index) / float(uSampleRate)), -1.0, 1.0);
+}}
+"#,
+        source
+    )
+}
+
+/// Write interleaved stereo samples to a 16 bits WAV file
+pub fn write_wav(path: &Path, samples: &[f32], sample_rate: u32) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
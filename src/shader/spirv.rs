@@ -0,0 +1,166 @@
+//! Load spirv modules and check they comply with the interface expected by Nuance.
+//!
+//! There is no source code to read settings from, params only come from the debug names of the
+//! uniform block bound at the params binding.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use naga::front::spv::{Options, Parser};
+use naga::{
+    ImageClass, ImageDimension, Module, ResourceBinding, ScalarKind, StorageClass, TypeInner,
+};
+
+use crate::shader::reflection::{self, Annotation};
+use crate::ShaderMetadata;
+
+/// The first word of any spirv module
+pub const MAGIC_NUMBER: u32 = 0x0723_0203;
+
+/// Expected layout of the globals push constant block : offset, kind and number of components
+/// of each member.
+const GLOBALS_LAYOUT: &[(u32, ScalarKind, u32)] = &[
+    // uResolution
+    (0, ScalarKind::Uint, 2),
+    // uMouse
+    (8, ScalarKind::Uint, 2),
+    // fMouseWheel
+    (16, ScalarKind::Float, 1),
+    // fRatio
+    (20, ScalarKind::Float, 1),
+    // fTime
+    (24, ScalarKind::Float, 1),
+    // uFrame
+    (28, ScalarKind::Uint, 1),
+    // fTimeDelta
+    (32, ScalarKind::Float, 1),
+    // vDate
+    (48, ScalarKind::Float, 4),
+    // fBass
+    (64, ScalarKind::Float, 1),
+    // fMid
+    (68, ScalarKind::Float, 1),
+    // fTreble
+    (72, ScalarKind::Float, 1),
+];
+
+/// Read a spirv binary as little endian words
+pub fn read_words(bytes: &[u8]) -> Result<Vec<u32>> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(anyhow!(
+            "Invalid spirv module : size is not a multiple of 4 bytes"
+        ));
+    }
+    let words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|it| u32::from_le_bytes([it[0], it[1], it[2], it[3]]))
+        .collect();
+    match words.first() {
+        Some(&MAGIC_NUMBER) => Ok(words),
+        Some(&other) if other.swap_bytes() == MAGIC_NUMBER => Err(anyhow!(
+            "Invalid spirv module : big endian modules are not supported"
+        )),
+        _ => Err(anyhow!("Invalid spirv module : wrong magic number")),
+    }
+}
+
+/// Parse a spirv module with naga
+pub fn parse(words: &[u32]) -> Result<Module> {
+    Parser::new(words.iter().cloned(), &Options::default())
+        .parse()
+        .map_err(|e| anyhow!("Can't parse spirv module : {}", e))
+}
+
+/// Check the module interface and extract params from it.
+/// Params settings can be given when the source code is available.
+pub fn extract(
+    module: &Module,
+    annotations: &HashMap<String, Vec<Annotation>>,
+) -> Result<ShaderMetadata> {
+    check_globals(module)?;
+    check_previous_frame(module)?;
+
+    let mut metadata = ShaderMetadata::default();
+    if let Some(params) = reflection::find_params_block(module) {
+        metadata.sliders = reflection::create_sliders(module, params.ty, annotations)
+            .map_err(|e| anyhow!("{} (was the module compiled with debug names ?)", e))?;
+    }
+    Ok(metadata)
+}
+
+/// The push constant block, if any, must be a prefix of Globals
+fn check_globals(module: &Module) -> Result<()> {
+    let globals = match module
+        .global_variables
+        .iter()
+        .find(|(_, var)| var.class == StorageClass::PushConstant)
+    {
+        Some((_, var)) => var,
+        None => return Ok(()),
+    };
+
+    let members = reflection::block_members(module, globals.ty)
+        .ok_or_else(|| anyhow!("The push constant block must be a struct"))?;
+    if members.len() > GLOBALS_LAYOUT.len() {
+        return Err(anyhow!(
+            "The push constant block has {} members, Globals only has {}",
+            members.len(),
+            GLOBALS_LAYOUT.len()
+        ));
+    }
+
+    for (i, (member, &(offset, kind, components))) in
+        members.iter().zip(GLOBALS_LAYOUT.iter()).enumerate()
+    {
+        let matches = member.offset == offset
+            && match module.types[member.ty].inner {
+                TypeInner::Scalar { kind: k, width: 4 } => k == kind && components == 1,
+                TypeInner::Vector {
+                    size,
+                    kind: k,
+                    width: 4,
+                } => k == kind && size as u32 == components,
+                _ => false,
+            };
+        if !matches {
+            return Err(anyhow!(
+                "Member {} ({}) of the push constant block does not match Globals",
+                i,
+                member.name.as_deref().unwrap_or("unnamed")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The previous frame texture and sampler must be bound at set 0
+fn check_previous_frame(module: &Module) -> Result<()> {
+    for (_, var) in module.global_variables.iter() {
+        let binding = match &var.binding {
+            Some(binding @ ResourceBinding { group: 0, .. }) => binding,
+            _ => continue,
+        };
+        let valid = matches!(
+            (binding.binding, &module.types[var.ty].inner),
+            (
+                0,
+                TypeInner::Image {
+                    dim: ImageDimension::D2,
+                    arrayed: false,
+                    class: ImageClass::Sampled {
+                        kind: ScalarKind::Float,
+                        multi: false,
+                    },
+                },
+            ) | (1, TypeInner::Sampler { comparison: false })
+        );
+        if !valid {
+            return Err(anyhow!(
+                "Invalid binding {} ({}) at set 0, only the previous frame texture (binding 0) and its sampler (binding 1) are allowed",
+                binding.binding,
+                var.name.as_deref().unwrap_or("unnamed")
+            ));
+        }
+    }
+    Ok(())
+}
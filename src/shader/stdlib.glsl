@@ -9,27 +9,10 @@
 // Current fragment coordinates in normalized space
 #define fragCoordNorm fragCoord.xy / uResolution
 
-// Current fragment output color
-layout(location = 0) out vec4 fragColor;
-
-layout(set = 0, binding = 0) uniform texture2D lastFrame;
-layout(set = 0, binding = 1) uniform sampler lastFrameSampler;
-
-// Globals are variables your shader can access
-layout(push_constant) uniform Globals {
-// Window resolution
-    uvec2 uResolution;
-// Mouse position
-    uvec2 uMouse;
-// Mouse wheel
-    float fMouseWheel;
-// Aspect ratio
-    float fRatio;
-// Time in sec
-    float fTime;
-// The number of frame we're at
-    uint uFrame;
-};
+#include <NuanceInterface>
+
+// Channels, sampled with uChannel0 to uChannel3
+#include <NuanceChannels>
 
 #define FIRST_RUN uFrame == 0
 
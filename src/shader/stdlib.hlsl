@@ -0,0 +1,53 @@
+#define NUANCE
+
+#include <noise>
+
+// Globals are variables your shader can access
+[[vk::push_constant]]
+cbuffer Globals {
+// Window resolution
+    uint2 uResolution;
+// Mouse position
+    uint2 uMouse;
+// Mouse wheel
+    float fMouseWheel;
+// Aspect ratio
+    float fRatio;
+// Time in sec
+    float fTime;
+// The number of frame we're at
+    uint uFrame;
+// Time since the last frame in sec
+    float fTimeDelta;
+// Date (UTC) : year, month (0-11), day, seconds since midnight
+    float4 vDate;
+// Levels of the bass, mid and treble bands of the first audio channel (0-1)
+    float fBass;
+    float fMid;
+    float fTreble;
+};
+
+[[vk::binding(0, 0)]] Texture2D<float4> lastFrame;
+[[vk::binding(1, 0)]] SamplerState lastFrameSampler;
+
+// Channels, bound to textures or to the output of passes
+[[vk::binding(0, 2)]] Texture2D<float4> uChannel0;
+[[vk::binding(1, 2)]] SamplerState uChannelSampler0;
+[[vk::binding(2, 2)]] Texture2D<float4> uChannel1;
+[[vk::binding(3, 2)]] SamplerState uChannelSampler1;
+[[vk::binding(4, 2)]] Texture2D<float4> uChannel2;
+[[vk::binding(5, 2)]] SamplerState uChannelSampler2;
+[[vk::binding(6, 2)]] Texture2D<float4> uChannel3;
+[[vk::binding(7, 2)]] SamplerState uChannelSampler3;
+
+#define FIRST_RUN (uFrame == 0)
+
+// Sample the last frame at the given normalized coordinates
+float4 samplePreviousN(float2 st) {
+    return lastFrame.Sample(lastFrameSampler, st);
+}
+
+// Sample last frame at the given coordinates in pixel coordinates
+float4 samplePrevious(float2 xy) {
+    return samplePreviousN(xy / float2(uResolution));
+}
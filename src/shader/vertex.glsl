@@ -0,0 +1,33 @@
+#define NUANCE
+
+#version 460
+
+#include <noise>
+
+// The vertex count follows the globals in the push constants
+#define NUANCE_VERTEX_GLOBALS
+#include <NuanceGlobals>
+
+// Channels, sampled with uChannel0 to uChannel3 and textureLod in the vertex stage
+#include <NuanceChannels>
+
+#define FIRST_RUN uFrame == 0
+
+#ifdef NUANCE_FRAGMENT
+
+// Color of the current fragment, interpolated between the vertices of the primitive
+layout(location = 0) in vec4 vColor;
+// Current fragment output color
+layout(location = 0) out vec4 fragColor;
+
+#else
+
+// Color of the current vertex
+layout(location = 0) out vec4 vColor;
+
+// Index of the current vertex
+#define vertexId float(gl_VertexIndex)
+// Number of vertices drawn
+#define vertexCount float(uVertexCount)
+
+#endif
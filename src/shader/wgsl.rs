@@ -0,0 +1,83 @@
+//! Extract information from wgsl source.
+//!
+//! Params are declared with a struct bound at `[[group(1), binding(0)]]`, each member can be
+//! annotated in a comment on the same line or on the lines before.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use naga::Module;
+
+use crate::shader::reflection::{self, Annotation};
+use crate::ShaderMetadata;
+
+/// Parse a wgsl module with naga
+pub fn parse(source: &str) -> Result<Module> {
+    naga::front::wgsl::parse_str(source).map_err(|e| anyhow!("{}", e.emit_to_string(source)))
+}
+
+/// Extract params from the module, their settings are read from the source comments
+pub fn extract(source: &str, module: &Module) -> Result<ShaderMetadata> {
+    let mut metadata = ShaderMetadata::default();
+
+    if let Some(params) = reflection::find_params_block(module) {
+        let annotations = module.types[params.ty]
+            .name
+            .as_ref()
+            .map(|name| member_annotations(source, name))
+            .unwrap_or_default();
+        debug!("params annotations : {:?}", annotations);
+        metadata.sliders = reflection::create_sliders(module, params.ty, &annotations)?;
+    }
+
+    Ok(metadata)
+}
+
+/// Collect annotations on the members of the struct with the given name
+pub fn member_annotations(source: &str, struct_name: &str) -> HashMap<String, Vec<Annotation>> {
+    reflection::find_block_body(source, "struct", struct_name)
+        .map(|body| {
+            reflection::member_annotations(body, |code| {
+                // Strip attributes, members are declared as `name: type;`
+                let code = match code.rfind("]]") {
+                    Some(i) => &code[i + 2..],
+                    None => code,
+                };
+                code.split_once(':').map(|(name, _)| name.trim())
+            })
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Slider;
+
+    #[test]
+    fn sliders_from_annotations() {
+        let source = "struct Params {
+    intensity: f32; // @range(0, 2) @init(1.5)
+    // @bool
+    inverted: u32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> params: Params;
+
+[[stage(fragment)]]
+fn main() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(params.intensity, f32(params.inverted), 0.0, 1.0);
+}
+";
+        let metadata = extract(source, &parse(source).unwrap()).unwrap();
+        match &metadata.sliders[0] {
+            Slider::Float {
+                min, max, value, ..
+            } => assert_eq!((*min, *max, *value), (0.0, 2.0, 1.5)),
+            other => panic!("Expected a float slider, got {}", other.name()),
+        }
+        assert!(matches!(metadata.sliders[1], Slider::Bool { .. }));
+    }
+}